@@ -0,0 +1,127 @@
+use std::{error::Error, fs, process::ExitCode};
+use cell::level::{
+    CAMPAIGN_PATH, CampaignManifest, LevelGrid, Tile,
+    get_first_row_line, get_image_palette, parse_level, parse_level_image, parse_tiled_json, parse_tiled_xml};
+
+/// Checks every level in the campaign without opening a window, printing
+/// one line per problem and failing if there were any.
+fn main() -> ExitCode {
+    let manifest_path = format!("assets/{CAMPAIGN_PATH}");
+    let manifest = match read_manifest(&manifest_path) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            eprintln!("{manifest_path}: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut problem_count = 0;
+    for level in &manifest.levels {
+        let path = format!("assets/{}", level.path);
+        let problems = match read_level(&path) {
+            Ok((grid, first_line)) => lint_level(&grid, first_line),
+            Err(error) => vec![error.to_string()],
+        };
+        for problem in &problems {
+            println!("{path}: {problem}");
+        }
+        problem_count += problems.len();
+    }
+    if problem_count > 0 {
+        println!("{problem_count} problem(s) in {} level(s)", manifest.levels.len());
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Fails on a manifest the game's loader would reject.
+fn read_manifest(path: &str) -> Result<CampaignManifest, Box<dyn Error>> {
+    let manifest: CampaignManifest = ron::from_str(&fs::read_to_string(path)?)?;
+    manifest.validate()?;
+    Ok(manifest)
+}
+
+/// The grid with the line of the file its top row is on, which is 1 for
+/// anything but a text level.
+fn read_level(path: &str) -> Result<(LevelGrid, usize), Box<dyn Error>> {
+    if path.ends_with(".lvl.png") {
+        // Without the game's prefabs, images are read in their default colors.
+        Ok((parse_level_image(&fs::read(path)?, &get_image_palette(|_| None))?, 1))
+    } else if path.ends_with(".tmx") {
+        Ok((parse_tiled_xml(&fs::read_to_string(path)?)?, 1))
+    } else if path.ends_with(".tmj") {
+        Ok((parse_tiled_json(&fs::read_to_string(path)?)?, 1))
+    } else {
+        let source = fs::read_to_string(path)?;
+        Ok((parse_level(&source)?, get_first_row_line(&source)))
+    }
+}
+
+/// Rows are reported as lines of the file, counting from `first_line` for
+/// the grid's top row, and columns 1-based, as `parse_level` does.
+fn lint_level(grid: &LevelGrid, first_line: usize) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (col, row, tile) in grid.iter() {
+        let is_edge = col == 0 || row == 0 || col == grid.width - 1 || row == grid.height - 1;
+        if is_edge && tile != Tile::Wall {
+            problems.push(format!(
+                "missing border wall at row {}, column {}", row + first_line, col + 1));
+        }
+    }
+    // A parsed level always has exactly one player.
+    let Some((col, row, _)) = grid.iter().find(|(_, _, tile)| *tile == Tile::Player) else {
+        return problems;
+    };
+    let reached = grid.flood_fill(col, row);
+    // Particles cross membranes, so enemies behind one can still be shot
+    // and the space behind it is not closed off.
+    let shot = grid.flood_fill_by(col, row, Tile::blocks_particles);
+    for (col, row, tile) in grid.iter() {
+        let i = row * grid.width + col;
+        if tile == Tile::Floor || tile.is_wall() || reached[i] || (tile == Tile::Enemy && shot[i]) {
+            continue;
+        }
+        problems.push(format!(
+            "unreachable '{}' at row {}, column {}", tile.glyph(), row + first_line, col + 1));
+    }
+    let mut visited = shot;
+    for (col, row, tile) in grid.iter() {
+        if tile.blocks_particles() || visited[row * grid.width + col] {
+            continue;
+        }
+        let region = grid.flood_fill_by(col, row, Tile::blocks_particles);
+        let size = region.iter().filter(|reached| **reached).count();
+        for (visited, reached) in visited.iter_mut().zip(region) {
+            *visited |= reached;
+        }
+        problems.push(format!(
+            "closed-off region of {size} tile(s) at row {}, column {}", row + first_line, col + 1));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_campaign_is_clean() {
+        let manifest = read_manifest(&format!("assets/{CAMPAIGN_PATH}")).unwrap();
+        for level in &manifest.levels {
+            let (grid, first_line) = read_level(&format!("assets/{}", level.path)).unwrap();
+            assert_eq!(lint_level(&grid, first_line), Vec::<String>::new(), "{}", level.path);
+        }
+    }
+
+    #[test]
+    fn broken_level_is_reported() {
+        let source = "name = Broken\n---\n|||||||\n|P.|.E|\n|||||..\n";
+        let grid = parse_level(source).unwrap();
+        assert_eq!(lint_level(&grid, get_first_row_line(source)), vec![
+            "missing border wall at row 5, column 6".to_string(),
+            "missing border wall at row 5, column 7".to_string(),
+            "unreachable 'E' at row 4, column 6".to_string(),
+            "closed-off region of 4 tile(s) at row 4, column 5".to_string(),
+        ]);
+    }
+}
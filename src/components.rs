@@ -1,39 +1,72 @@
-use bevy::prelude::{Component, Timer, Vec3};
+use bevy::prelude::{Component, FromReflect, Reflect, ReflectComponent, Timer, Vec3};
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Cell;
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Player;
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Lifespan(pub i32);
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Wall;
 
-#[derive(Component)]
+/// The `(col, row)` of the level tile an entity stands on.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct TilePosition(pub usize, pub usize);
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Enemy(pub Timer);
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct MainCamera;
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Hostile;
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Explosion(pub Timer);
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Virus(pub Timer);
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Particle;
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Velocity(pub Vec3);
 
-#[derive(Component, Clone)]
+/// A pickup that puts its effect on the cell that touches it.
+#[derive(Component, Reflect, FromReflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[reflect(Component)]
 pub enum StatusEffect {
+    #[default]
     Speed,
 }
+
+/// One effect a cell is under, with how many times it has stacked and
+/// how long it has left.
+#[derive(Reflect, FromReflect, Default, Clone)]
+pub struct ActiveEffect {
+    pub effect: StatusEffect,
+    pub stacks: u32,
+    pub timer: Timer,
+}
+
+/// The effects a cell is under, at most one entry per kind of effect.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct StatusEffects(pub Vec<ActiveEffect>);
@@ -0,0 +1,405 @@
+use bevy::{prelude::*, tasks::IoTaskPool};
+use std::{fs::File, io::Write, sync::{Arc, Mutex}};
+use crate::AppState;
+use crate::components::MainCamera;
+use crate::level::{
+    Campaign, LevelAsset, LevelGrid, LevelSource, MEMBRANE_COLOR, Tile, WEAK_WALL_COLOR,
+    frame_camera, get_chunks, parse_level, remove_level_grid};
+use crate::menu::ui::get_text_bundle;
+use crate::util::despawn_screen;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<Editor>()
+        .add_system_set(SystemSet::on_enter(AppState::Editor)
+            .with_system(open_level)
+            .with_system(spawn_ui))
+        .add_system_set(SystemSet::on_update(AppState::Editor)
+            .with_system(input_brush)
+            .with_system(input_edit)
+            .with_system(input_file)
+            .with_system(update_save_status)
+            .with_system(paint_tile)
+            .with_system(draw_grid)
+            .with_system(update_ui)
+            .with_system(frame_camera))
+        .add_system_set(SystemSet::on_exit(AppState::Editor)
+            .with_system(despawn_screen::<EditorTile>)
+            .with_system(despawn_screen::<EditorUi>)
+            .with_system(remove_level_grid));
+    }
+}
+
+const PALETTE: [(KeyCode, Tile); 7] = [
+    (KeyCode::Key1, Tile::Wall),
+    (KeyCode::Key2, Tile::Floor),
+    (KeyCode::Key3, Tile::Player),
+    (KeyCode::Key4, Tile::Enemy),
+    (KeyCode::Key5, Tile::Speed),
+    (KeyCode::Key6, Tile::Membrane),
+    (KeyCode::Key7, Tile::WeakWall),
+];
+
+const TOOLS: [(KeyCode, Tool); 3] = [
+    (KeyCode::B, Tool::Brush),
+    (KeyCode::R, Tool::Rectangle),
+    (KeyCode::F, Tool::Fill),
+];
+
+const HISTORY_SIZE: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tool {
+    Brush,
+    Rectangle,
+    Fill,
+}
+
+/// The level file being edited, by index into the campaign.
+#[derive(Resource)]
+struct Editor {
+    index: usize,
+    brush: Tile,
+    tool: Tool,
+    /// Where the current rectangle was started.
+    anchor: Option<(usize, usize)>,
+    undo: Vec<LevelGrid>,
+    redo: Vec<LevelGrid>,
+    /// Whether the stroke being drawn already has its undo entry.
+    is_stroke_open: bool,
+    status: String,
+    /// The outcome of the last save, filled in by the I/O task writing it.
+    save_status: Arc<Mutex<Option<String>>>,
+}
+
+impl Default for Editor {
+    fn default() -> Editor {
+        Editor {
+            index: 0,
+            brush: Tile::Wall,
+            tool: Tool::Brush,
+            anchor: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            is_stroke_open: false,
+            status: String::new(),
+            save_status: default(),
+        }
+    }
+}
+
+impl Editor {
+    fn save_history(&mut self, grid: &LevelGrid) {
+        if self.undo.len() == HISTORY_SIZE {
+            self.undo.remove(0);
+        }
+        self.undo.push(grid.clone());
+        self.redo.clear();
+    }
+
+    /// Saves history before the first tile a stroke changes, so strokes
+    /// dragged in from outside the grid can be undone too.
+    fn open_stroke(&mut self, grid: &LevelGrid) {
+        if !self.is_stroke_open {
+            self.save_history(grid);
+            self.is_stroke_open = true;
+        }
+    }
+
+    /// Drops strokes that changed nothing so undo never restores a no-op.
+    fn close_stroke(&mut self, grid: &LevelGrid) {
+        if self.is_stroke_open && self.undo.last() == Some(grid) {
+            self.undo.pop();
+        }
+        self.is_stroke_open = false;
+    }
+}
+
+#[derive(Component)]
+struct EditorTile;
+
+#[derive(Component)]
+struct EditorUi;
+
+fn open_level(
+    mut commands: Commands,
+    mut buttons: ResMut<Input<MouseButton>>,
+    mut editor: ResMut<Editor>,
+    mut source: ResMut<LevelSource>,
+    campaign: Res<Campaign>,
+    levels: Res<Assets<LevelAsset>>,
+) {
+    // The click that opened the editor should not paint.
+    buttons.reset(MouseButton::Left);
+    // Coming back from a test play keeps the unsaved grid and its history.
+    let grid = match std::mem::take(&mut *source) {
+        LevelSource::Playtest(grid) => grid,
+        _ => {
+            editor.undo.clear();
+            editor.redo.clear();
+            get_grid(editor.index, &campaign, &levels)
+        }
+    };
+    commands.insert_resource(grid);
+}
+
+fn get_grid(index: usize, campaign: &Campaign, levels: &Assets<LevelAsset>) -> LevelGrid {
+    levels.get(&campaign.handles[index])
+        .map(|asset| asset.0.clone())
+        .unwrap_or_else(|| LevelGrid::filled(21, 21, Tile::Floor))
+}
+
+fn spawn_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        get_text_bundle(Color::WHITE, 16.0, "", &asset_server)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        EditorUi,
+    ));
+}
+
+fn update_ui(
+    editor: Res<Editor>,
+    campaign: Res<Campaign>,
+    mut query: Query<&mut Text, With<EditorUi>>,
+) {
+    let value = format!(
+        "{}  {:?} {:?}\n1-{} brush  B/R/F tool  S save  T test play\n\
+        Ctrl+Z/Y undo/redo  PgUp/PgDn level  Back exit\n{}",
+        campaign.levels[editor.index].path, editor.tool, editor.brush,
+        PALETTE.len(), editor.status);
+    for mut text in &mut query {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+fn input_brush(key: Res<Input<KeyCode>>, mut editor: ResMut<Editor>) {
+    for (key_code, tile) in PALETTE {
+        if key.just_pressed(key_code) {
+            editor.brush = tile;
+        }
+    }
+    for (key_code, tool) in TOOLS {
+        if key.just_pressed(key_code) {
+            editor.tool = tool;
+        }
+    }
+}
+
+fn input_edit(
+    mut state: ResMut<State<AppState>>,
+    mut source: ResMut<LevelSource>,
+    key: Res<Input<KeyCode>>,
+    mut editor: ResMut<Editor>,
+    grid: Option<ResMut<LevelGrid>>,
+) {
+    let Some(mut grid) = grid else { return };
+    let is_control_pressed = key.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    if is_control_pressed && key.just_pressed(KeyCode::Z) {
+        if let Some(previous) = editor.undo.pop() {
+            let current = std::mem::replace(&mut *grid, previous);
+            editor.redo.push(current);
+        }
+    }
+    if is_control_pressed && key.just_pressed(KeyCode::Y) {
+        if let Some(next) = editor.redo.pop() {
+            let current = std::mem::replace(&mut *grid, next);
+            editor.undo.push(current);
+        }
+    }
+    if key.just_pressed(KeyCode::T) {
+        match parse_level(&grid.to_string()) {
+            // A transition already queued this frame, such as Backspace, wins.
+            Ok(_) => if state.set(AppState::Game).is_ok() {
+                *source = LevelSource::Playtest(grid.clone());
+            }
+            Err(error) => editor.status = format!("cannot play: {error}"),
+        }
+    }
+}
+
+fn input_file(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    key: Res<Input<KeyCode>>,
+    mut editor: ResMut<Editor>,
+    grid: Option<Res<LevelGrid>>,
+    campaign: Res<Campaign>,
+    levels: Res<Assets<LevelAsset>>,
+) {
+    if let Some(grid) = grid.filter(|_| key.just_pressed(KeyCode::S)) {
+        let save_status = editor.save_status.clone();
+        editor.status = save_level(&grid, campaign.levels[editor.index].path.clone(), save_status);
+    }
+    if key.any_just_pressed([KeyCode::PageUp, KeyCode::PageDown]) {
+        editor.index = if key.just_pressed(KeyCode::PageUp) {
+            (editor.index + campaign.levels.len() - 1) % campaign.levels.len()
+        } else {
+            (editor.index + 1) % campaign.levels.len()
+        };
+        editor.status.clear();
+        commands.insert_resource(get_grid(editor.index, &campaign, &levels));
+    }
+    if key.just_pressed(KeyCode::Back) {
+        // A test play queued this frame, with T, wins.
+        state.set(AppState::Splash).ok();
+    }
+}
+
+/// Refuses to write levels that `parse_level` would reject, or over
+/// levels imported from images. The write itself reports back through
+/// `save_status` once it is done.
+fn save_level(grid: &LevelGrid, path: String, save_status: Arc<Mutex<Option<String>>>) -> String {
+    if !path.ends_with(".lvl") {
+        return format!("not saved: {path} is not a text level");
+    }
+    let source = grid.to_string();
+    if let Err(error) = parse_level(&source) {
+        return format!("not saved: {error}");
+    }
+    let message = format!("saving {path}");
+    IoTaskPool::get().spawn(async move {
+        let result = File::create(format!("assets/{path}"))
+            .and_then(|mut file| file.write_all(source.as_bytes()));
+        let status = match result {
+            Ok(()) => format!("saved {path}"),
+            Err(error) => format!("not saved: {error}"),
+        };
+        if let Ok(mut save_status) = save_status.lock() {
+            *save_status = Some(status);
+        }
+    }).detach();
+    message
+}
+
+fn update_save_status(mut editor: ResMut<Editor>) {
+    let status = editor.save_status.lock().ok().and_then(|mut status| status.take());
+    if let Some(status) = status {
+        editor.status = status;
+    }
+}
+
+fn paint_tile(
+    windows: Res<Windows>,
+    buttons: Res<Input<MouseButton>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut editor: ResMut<Editor>,
+    grid: Option<ResMut<LevelGrid>>,
+) {
+    let Some(mut grid) = grid else { return };
+    let (camera, transform) = camera_query.single();
+    let window = windows.get_primary().unwrap();
+    let cursor_tile = window.cursor_position()
+        .and_then(|cursor| camera.viewport_to_world(transform, cursor))
+        .and_then(|ray| grid.world_to_tile(ray.origin));
+    for (button, tile) in [(MouseButton::Left, editor.brush), (MouseButton::Right, Tile::Floor)] {
+        if let Some((col, row)) = cursor_tile {
+            paint_tile_at(&mut editor, &mut grid, &buttons, button, tile, (col, row));
+        }
+        // Released off the grid as well, so the next stroke gets its own entry.
+        if buttons.just_released(button) {
+            editor.close_stroke(&grid);
+        }
+    }
+}
+
+fn paint_tile_at(
+    editor: &mut Editor,
+    grid: &mut LevelGrid,
+    buttons: &Input<MouseButton>,
+    button: MouseButton,
+    tile: Tile,
+    (col, row): (usize, usize),
+) {
+    if buttons.just_pressed(button) {
+        editor.anchor = Some((col, row));
+    }
+    // A level has exactly one player spawn, so it is always placed singly.
+    let tool = if tile == Tile::Player { Tool::Brush } else { editor.tool };
+    match tool {
+        Tool::Brush if buttons.pressed(button) && grid.get(col, row) != Some(tile) => {
+            editor.open_stroke(grid);
+            if tile == Tile::Player {
+                for player in grid.tiles.iter_mut().filter(|tile| **tile == Tile::Player) {
+                    *player = Tile::Floor;
+                }
+            }
+            grid.set(col, row, tile);
+        }
+        Tool::Rectangle if buttons.just_released(button) => {
+            if let Some((anchor_col, anchor_row)) = editor.anchor.take() {
+                editor.open_stroke(grid);
+                for fill_row in anchor_row.min(row)..=anchor_row.max(row) {
+                    for fill_col in anchor_col.min(col)..=anchor_col.max(col) {
+                        grid.set(fill_col, fill_row, tile);
+                    }
+                }
+            }
+        }
+        Tool::Fill if buttons.just_pressed(button) => {
+            editor.open_stroke(grid);
+            fill(grid, col, row, tile);
+        }
+        _ => {}
+    }
+}
+
+/// Replaces the 4-connected region of tiles matching `(col, row)` with `tile`.
+fn fill(grid: &mut LevelGrid, col: usize, row: usize, tile: Tile) {
+    let Some(target) = grid.get(col, row).filter(|target| *target != tile) else { return };
+    let mut stack = vec![(col, row)];
+    while let Some((col, row)) = stack.pop() {
+        if grid.get(col, row) != Some(target) {
+            continue;
+        }
+        grid.set(col, row, tile);
+        stack.push((col + 1, row));
+        stack.push((col, row + 1));
+        if col > 0 {
+            stack.push((col - 1, row));
+        }
+        if row > 0 {
+            stack.push((col, row - 1));
+        }
+    }
+}
+
+fn draw_grid(
+    mut commands: Commands,
+    grid: Option<Res<LevelGrid>>,
+    mut images: ResMut<Assets<Image>>,
+    query: Query<Entity, With<EditorTile>>,
+) {
+    let Some(grid) = grid.filter(|grid| grid.is_changed()) else { return };
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    for chunk in get_chunks(&grid, &mut images, get_tile_color) {
+        commands.spawn((chunk, EditorTile));
+    }
+}
+
+fn get_tile_color(tile: Tile) -> Option<Color> {
+    match tile {
+        Tile::Floor => Some(Color::rgb(0.1, 0.1, 0.1)),
+        Tile::Wall => Some(Color::DARK_GRAY),
+        Tile::Membrane => Some(MEMBRANE_COLOR),
+        Tile::WeakWall => Some(WEAK_WALL_COLOR),
+        Tile::Player => Some(Color::ORANGE_RED),
+        Tile::Enemy => Some(Color::FUCHSIA),
+        Tile::Speed => Some(Color::GREEN),
+        Tile::Custom(_) => Some(Color::WHITE),
+    }
+}
@@ -1,4 +1,5 @@
-use bevy::prelude::{Vec3, Vec2};
+use bevy::prelude::{Entity, Vec3, Vec2};
+use crate::components::StatusEffect;
 
 pub struct MenuEvent(pub String);
 
@@ -14,3 +15,9 @@ pub struct ExplodeEvent {
 pub struct DropVirusEvent {
     pub translation: Vec3,
 }
+
+/// Sent when a status effect on `entity` runs out, which removes it.
+pub struct EffectExpiredEvent {
+    pub entity: Entity,
+    pub effect: StatusEffect,
+}
@@ -1,14 +1,29 @@
 use bevy::{
     prelude::*, 
     sprite::collide_aabb::collide};
-use crate::{SCREEN_WIDTH, SCREEN_HEIGHT, AppState};
+use crate::AppState;
 use crate::components::*;
 use crate::events::*;
-use crate::level::{Level, generate_level};
+use crate::level::{
+    Campaign, EnemiesRemaining, LevelGrid, LevelSource, Tile, WallHealth, generate_level,
+    move_bouncing, sweep_boundary};
 use crate::util::despawn_screen;
-use rand::prelude::random;
 
 const CELL_SIZE: f32 = 20.0;
+const PLAYER_SPEED: f32 = 60.0;
+/// Added to the player's speed by each stack of `StatusEffect::Speed`.
+const SPEED_BONUS: f32 = 90.0;
+/// Fraction of their speed particles keep while passing through a membrane.
+const MEMBRANE_SPEED: f32 = 0.4;
+/// Membrane edges a particle crosses in one frame before the rest of its
+/// move is cut short.
+const MAX_MEMBRANE_CROSSINGS: usize = 4;
+const EXPLOSION_SIZE: f32 = 10.0;
+/// Large sprites are drawn and collide at this many times their size.
+const LARGE_SPRITE_SCALE: f32 = 10.0;
+
+pub const PARTICLE_TEXTURE: &str = "components/particle.png";
+pub const EXPLOSION_TEXTURE: &str = "components/explosion.png";
 
 pub struct GamePlugin;
 
@@ -19,14 +34,16 @@ impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app
         .insert_resource(Score(0))
-        .insert_resource(Level(3))
         .add_event::<MenuEvent>()
         .add_event::<DropVirusEvent>()
         .add_event::<EjectEvent>()
         .add_event::<ExplodeEvent>()
+        .add_event::<EffectExpiredEvent>()
         .add_startup_system(spawn_camera)
         .add_system_set(SystemSet::on_enter(AppState::Game)
             .with_system(reset_score)
+            .with_system(reset_enemies)
+            .with_system(start_campaign.before(generate_level))
             .with_system(generate_level))
         .add_system_set(SystemSet::on_pause(AppState::Game)
             .with_system(despawn_screen::<Wall>)
@@ -36,8 +53,10 @@ impl Plugin for GamePlugin {
             .with_system(despawn_screen::<StatusEffect>)
             .with_system(despawn_screen::<Explosion>))
         .add_system_set(SystemSet::on_resume(AppState::Game)
+            .with_system(advance_campaign.before(generate_level))
             .with_system(generate_level))
         .add_system_set(SystemSet::on_update(AppState::Game)
+            .with_system(tick_campaign)
             .with_system(input_player)
             .with_system(input_particle)
             .with_system(input_virus)
@@ -51,8 +70,12 @@ impl Plugin for GamePlugin {
                     .after(despawn_virus))
             .with_system(despawn_virus)
             .with_system(despawn_explosion) 
+            .with_system(damage_walls)
             .with_system(despawn_lifespan) 
+            .with_system(complete_level.after(despawn_lifespan))
             .with_system(collide_status_effect) 
+            .with_system(tick_status_effects.after(collide_status_effect))
+            .with_system(expire_status_effects.after(tick_status_effects))
             .with_system(collide_hostile)
         ).add_system_set(SystemSet::on_exit(AppState::Game)
             .with_system(despawn_screen::<Wall>)
@@ -68,6 +91,39 @@ fn reset_score(mut score: ResMut<Score>) {
     score.0 = 0;
 }
 
+/// A count left over from the last game would end the new level before its
+/// enemies have spawned.
+fn reset_enemies(mut enemies: ResMut<EnemiesRemaining>) {
+    enemies.0 = 0;
+}
+
+/// Every game starts from its first level, including a generated or daily
+/// run played again from the menu, so that daily scores stay comparable.
+fn start_campaign(mut campaign: ResMut<Campaign>, mut source: ResMut<LevelSource>) {
+    campaign.index = 0;
+    source.restart();
+}
+
+/// Generated runs keep their own count and never touch the campaign.
+fn advance_campaign(
+    mut campaign: ResMut<Campaign>,
+    mut source: ResMut<LevelSource>,
+    score: Res<Score>,
+) {
+    match *source {
+        LevelSource::Assets => {
+            if let Some(next) = campaign.next(score.0) {
+                campaign.index = next;
+            }
+        }
+        _ => source.advance(),
+    }
+}
+
+fn tick_campaign(time: Res<Time>, mut campaign: ResMut<Campaign>) {
+    campaign.time += time.delta_seconds();
+}
+
 fn spawn_camera(mut commands: Commands) {
     commands.spawn((Camera2dBundle::default(), MainCamera));
 }
@@ -93,7 +149,7 @@ fn spawn_particle(
         let velocity = Vec3::new(x, y, 0.0).normalize();
         let particle_translation = event.translation + CELL_SIZE * velocity;
         let radius = 0.05;
-        let texture: Handle<Image> = asset_server.load("components/particle.png");
+        let texture: Handle<Image> = asset_server.load(PARTICLE_TEXTURE);
         let initial_velocity = 100.0;
         commands.spawn((
             get_sprite(radius, particle_translation, texture),
@@ -111,10 +167,9 @@ fn spawn_explosion(
     asset_server: Res<AssetServer>,
 ) {
     if let Some(event) = reader.iter().next() {
-        let radius = 10.0;
-        let texture: Handle<Image> = asset_server.load("components/explosion.png");
+        let texture: Handle<Image> = asset_server.load(EXPLOSION_TEXTURE);
         commands.spawn((
-            get_large_sprite(radius, event.translation, texture),
+            get_large_sprite(EXPLOSION_SIZE, event.translation, texture),
             Hostile,
             Explosion(Timer::from_seconds(1.0, TimerMode::Once)),
         ));
@@ -131,14 +186,14 @@ fn get_sprite(size: f32, translation: Vec3, texture: Handle<Image>) -> SpriteBun
 }
 
 fn get_large_sprite(size: f32, translation: Vec3, texture: Handle<Image>) -> SpriteBundle {
-    let limit = 10.0;
     SpriteBundle {
         sprite: Sprite {
-            custom_size: Some(Vec2::new(size / limit, size / limit)),
+            custom_size: Some(Vec2::new(size / LARGE_SPRITE_SCALE, size / LARGE_SPRITE_SCALE)),
             ..default()
         },
         texture,
-        transform: Transform::from_scale(Vec3::new(size * limit, size * limit, 0.0))
+        transform: Transform::from_scale(
+                Vec3::new(size * LARGE_SPRITE_SCALE, size * LARGE_SPRITE_SCALE, 0.0))
             .with_translation(translation),
         ..default()
     }
@@ -156,39 +211,58 @@ fn get_rectangle(color: Color, height: f32, width: f32, translation: Vec3) -> Sp
     }
 }
 
+/// Moves each particle in stretches that end where it enters or leaves a
+/// membrane, so it is slowed only while it is inside one.
 fn move_particle(
     time: Res<Time>, 
-    wall_query: Query<&Transform, (With<Wall>, Without<Particle>)>,
+    grid: Option<Res<LevelGrid>>,
     mut particle_query: Query<(&mut Transform, &mut Lifespan, &mut Velocity), With<Particle>>,
 ) {
+    let Some(grid) = grid else { return };
+    let is_membrane = |tile| tile == Tile::Membrane;
     for (mut transform, mut lifespan, mut velocity) in particle_query.iter_mut() {
-        let mut new_transform = transform.clone();
-        new_transform.translation += velocity.0 * time.delta_seconds();
-        let mut wall_iter = wall_query.iter()
-            .filter(|wall_transform| has_collided(&new_transform, wall_transform));
-        if let Some(wall_transform) = wall_iter.next() {
-            if transform.translation.x < (wall_transform.translation.x - wall_transform.scale.x / 2.0)
-                || transform.translation.x > (wall_transform.translation.x + wall_transform.scale.x / 2.0) {
-                velocity.0.x = -velocity.0.x;
-            } else {
-                velocity.0.y = -velocity.0.y;
+        let size = transform.scale.truncate();
+        let mut translation = transform.translation.truncate();
+        let mut remaining = time.delta_seconds();
+        for _ in 0..MAX_MEMBRANE_CROSSINGS {
+            // Stretches are measured as fractions of the full speed move left this frame.
+            let motion = velocity.0.truncate() * remaining;
+            let stretch = sweep_boundary(&grid, translation, size, motion, is_membrane).unwrap_or(1.0);
+            let middle = translation + motion * stretch / 2.0;
+            let is_in_membrane = grid.overlapping_walls(middle.extend(0.0), size)
+                .any(|(_, _, tile)| is_membrane(tile));
+            let speed = if is_in_membrane { MEMBRANE_SPEED } else { 1.0 };
+            let distance = stretch.min(speed);
+            let bounced = move_bouncing(
+                &grid,
+                translation,
+                size,
+                velocity.0.truncate(),
+                distance * remaining,
+                Tile::blocks_particles);
+            translation = bounced.translation;
+            velocity.0 = bounced.velocity.extend(velocity.0.z);
+            lifespan.0 -= bounced.bounces as i32;
+            remaining *= 1.0 - distance / speed;
+            if distance < stretch || stretch >= 1.0 {
+                break;
             }
-            lifespan.0 -= 1;
         }
-        transform.translation += velocity.0 * time.delta_seconds();
+        transform.translation = translation.extend(transform.translation.z);
     }
 }
 
 fn move_player(
     time: Res<Time>, 
-    wall_query: Query<&Transform, (With<Wall>, Without<Player>)>,
+    grid: Option<Res<LevelGrid>>,
     mut player_query: Query<(&mut Transform, &Velocity), (With<Player>, Changed<Velocity>)>,
 ) {
-    let (mut transform, velocity) = player_query.single_mut();
-    let mut new_transform = transform.clone();
+    let Some(grid) = grid else { return };
+    let Ok((mut transform, velocity)) = player_query.get_single_mut() else { return };
+    let mut new_transform = *transform;
     new_transform.translation += velocity.0 * time.delta_seconds();
-    let has_not_collided = !wall_query.iter()
-        .any(|wall_transform| has_collided(&new_transform, wall_transform));
+    let has_not_collided = grid.overlapping_walls(
+        new_transform.translation, new_transform.scale.truncate()).next().is_none();
     if has_not_collided {
         transform.translation = new_transform.translation;
     }
@@ -196,14 +270,11 @@ fn move_player(
 
 fn input_player(
     key: Res<Input<KeyCode>>, 
-    mut query: Query<(&mut Velocity, Option<&StatusEffect>), With<Player>>,
+    mut query: Query<(&mut Velocity, Option<&StatusEffects>), With<Player>>,
 ) {
-    let (mut velocity, status_effect) = query.single_mut();
-    let default_speed = if let Some(StatusEffect::Speed) = status_effect {
-        150.0
-    } else {
-        60.0
-    };
+    let Ok((mut velocity, effects)) = query.get_single_mut() else { return };
+    let stacks = effects.map_or(0, |effects| get_stacks(effects, StatusEffect::Speed));
+    let default_speed = PLAYER_SPEED + SPEED_BONUS * stacks as f32;
     if key.pressed(KeyCode::A) {
         velocity.0.x = -default_speed;
     }
@@ -229,6 +300,7 @@ fn input_particle(
     camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
     mut writer: EventWriter<EjectEvent>,
 ) {
+    let Ok(player) = player_query.get_single() else { return };
     if key.just_pressed(KeyCode::Space) {
         let (camera, transform) = camera_query.single();
         let window = windows.get_primary().unwrap();
@@ -236,7 +308,7 @@ fn input_particle(
             .and_then(|cursor| camera.viewport_to_world(transform, cursor))
             .map(|ray| ray.origin.truncate())
         {
-            let translation = player_query.single().translation;
+            let translation = player.translation;
             writer.send(EjectEvent { translation, target_position }); 
         }
     }
@@ -247,40 +319,71 @@ fn input_virus(
     query: Query<&Transform, With<Player>>,
     mut writer: EventWriter<DropVirusEvent>,
 ) {
+    let Ok(player) = query.get_single() else { return };
     if key.just_pressed(KeyCode::Q) {
-        let translation = query.single().translation;
-        writer.send(DropVirusEvent { translation });
+        writer.send(DropVirusEvent { translation: player.translation });
     }
 }
 
 fn despawn_lifespan(
     mut commands: Commands,
     mut state: ResMut<State<AppState>>,
-    mut level: ResMut<Level>,
     mut score: ResMut<Score>,
+    source: Res<LevelSource>,
     query: Query<(Entity, &Lifespan, Option<&Player>, Option<&Enemy>)>,
     mut writer: EventWriter<MenuEvent>,
 ) {
     for (entity, lifespan, player, enemy) in query.iter() {
         if lifespan.0 <= 0 {
             if player.is_some() {
-                level.0 = 0;
-                writer.send(MenuEvent("Game Over".to_string()));
-                state.set(AppState::Menu).unwrap(); 
-            } else if enemy.is_some() {
-                score.0 += 1;
-                if level.0 == 0 {
-                    writer.send(MenuEvent("Victory".to_string()));
+                if let LevelSource::Playtest(_) = *source {
+                    state.set(AppState::Editor).unwrap();
                 } else {
-                    writer.send(MenuEvent("Next Level".to_string()));
+                    writer.send(MenuEvent("Game Over".to_string()));
+                    state.set(AppState::Menu).unwrap(); 
                 }
-                state.push(AppState::Menu).unwrap(); 
+            } else if enemy.is_some() {
+                score.0 += 1;
             }
             commands.entity(entity).despawn();
         }
     }
 }
 
+fn complete_level(
+    mut state: ResMut<State<AppState>>,
+    mut enemies: ResMut<EnemiesRemaining>,
+    mut campaign: ResMut<Campaign>,
+    score: Res<Score>,
+    source: Res<LevelSource>,
+    query: Query<(), With<Enemy>>,
+    mut writer: EventWriter<MenuEvent>,
+) {
+    let remaining = query.iter().count();
+    // Only the last kill ends a level, not a level that has yet to spawn.
+    // A transition that is already queued, such as the player dying in the
+    // same frame, takes precedence and the level is left uncleared.
+    if remaining == 0 && enemies.0 > 0 {
+        if let LevelSource::Playtest(_) = *source {
+            state.set(AppState::Editor).ok();
+        } else if state.push(AppState::Menu).is_ok() {
+            // Only the campaign runs out of levels.
+            let is_campaign = matches!(*source, LevelSource::Assets);
+            if is_campaign {
+                campaign.clear_current();
+            }
+            if is_campaign && campaign.next(score.0).is_none() {
+                writer.send(MenuEvent("Victory".to_string()));
+            } else {
+                writer.send(MenuEvent("Next Level".to_string()));
+            }
+        }
+    }
+    if enemies.0 != remaining {
+        enemies.0 = remaining;
+    }
+}
+
 fn despawn_virus(
     mut commands: Commands, 
     time: Res<Time>, 
@@ -302,9 +405,10 @@ fn spawn_enemy_particle(
     mut enemy_query: Query<(&Transform, &mut Enemy)>,
     mut writer: EventWriter<EjectEvent>,
 ) {
+    let Ok(player) = player_query.get_single() else { return };
     for (transform, mut enemy) in enemy_query.iter_mut() {
         if enemy.0.tick(time.delta()).just_finished() {
-            let player_position = player_query.single().translation.truncate();
+            let player_position = player.translation.truncate();
             let translation = transform.translation;
             writer.send(EjectEvent { translation, target_position: player_position });
         }
@@ -323,21 +427,132 @@ fn despawn_explosion(
     } 
 }
 
+/// Each explosion takes one health from every weak wall its sprite covers
+/// when it goes off. A wall out of health turns into floor. Driven by the
+/// event rather than the `Explosion` entity so that loading a save with an
+/// explosion in it does not damage the walls a second time.
+fn damage_walls(
+    grid: Option<ResMut<LevelGrid>>,
+    health: Option<ResMut<WallHealth>>,
+    mut reader: EventReader<ExplodeEvent>,
+) {
+    let (Some(mut grid), Some(mut health)) = (grid, health) else { return };
+    let size = Vec2::splat(EXPLOSION_SIZE * LARGE_SPRITE_SCALE);
+    for event in reader.iter() {
+        let walls: Vec<(usize, usize)> = grid.overlapping_walls(event.translation, size)
+            .filter(|(_, _, tile)| *tile == Tile::WeakWall)
+            .map(|(col, row, _)| (col, row))
+            .collect();
+        for (col, row) in walls {
+            let Some(wall_health) = health.0.get_mut(&(col, row)) else { continue };
+            *wall_health -= 1;
+            if *wall_health <= 0 {
+                health.0.remove(&(col, row));
+                grid.set(col, row, Tile::Floor);
+            }
+        }
+    }
+}
+
+/// Each cell takes every pickup it touches this frame at once, so that
+/// a cell with no effects yet gets all of them in one `StatusEffects`.
 fn collide_status_effect(
     mut commands: Commands,
     status_effect_query: Query<(Entity, &Transform, &StatusEffect), Without<Cell>>,
-    mut cell_query: Query<(Entity, &Transform), (With<Cell>, Without<StatusEffect>)>,
+    mut cell_query: Query<(Entity, &Transform, Option<&mut StatusEffects>), With<Cell>>,
 ) {
-    for (status_effect_entity, status_effect_transform, status_effect) in status_effect_query.iter() {
-        for (cell_entity, cell_transform) in cell_query.iter_mut() {
-            if has_collided(status_effect_transform, cell_transform) {
-                commands.entity(cell_entity).insert(status_effect.clone());
-                commands.entity(status_effect_entity).despawn_recursive();
+    let mut taken: Vec<Entity> = Vec::new();
+    for (cell_entity, cell_transform, effects) in cell_query.iter_mut() {
+        let picked_up: Vec<(Entity, StatusEffect)> = status_effect_query.iter()
+            .filter(|(entity, transform, _)| {
+                !taken.contains(entity) && has_collided(transform, cell_transform)
+            })
+            .map(|(entity, _, status_effect)| (entity, *status_effect))
+            .collect();
+        if picked_up.is_empty() {
+            continue;
+        }
+        let mut new_effects = StatusEffects::default();
+        let effects = match effects {
+            Some(effects) => effects.into_inner(),
+            None => &mut new_effects,
+        };
+        for (status_effect_entity, status_effect) in picked_up {
+            add_status_effect(effects, status_effect);
+            commands.entity(status_effect_entity).despawn_recursive();
+            taken.push(status_effect_entity);
+        }
+        if !new_effects.0.is_empty() {
+            commands.entity(cell_entity).insert(new_effects);
+        }
+    }
+}
+
+/// Seconds an effect lasts and how many times it stacks. Picking up an
+/// effect already at its stack limit only restarts its timer.
+fn get_effect_rules(effect: StatusEffect) -> (f32, u32) {
+    match effect {
+        StatusEffect::Speed => (5.0, 2),
+    }
+}
+
+fn add_status_effect(effects: &mut StatusEffects, effect: StatusEffect) {
+    let (duration, max_stacks) = get_effect_rules(effect);
+    let timer = Timer::from_seconds(duration, TimerMode::Once);
+    match effects.0.iter_mut().find(|active| active.effect == effect) {
+        // One that ran out but has not been removed yet starts over.
+        Some(active) if active.timer.finished() => {
+            active.stacks = 1;
+            active.timer = timer;
+        }
+        Some(active) => {
+            active.stacks = (active.stacks + 1).min(max_stacks);
+            active.timer = timer;
+        }
+        None => effects.0.push(ActiveEffect { effect, stacks: 1, timer }),
+    }
+}
+
+fn get_stacks(effects: &StatusEffects, effect: StatusEffect) -> u32 {
+    effects.0.iter()
+        .find(|active| active.effect == effect)
+        .map_or(0, |active| active.stacks)
+}
+
+fn tick_status_effects(
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut StatusEffects)>,
+    mut writer: EventWriter<EffectExpiredEvent>,
+) {
+    for (entity, mut effects) in query.iter_mut() {
+        for active in effects.0.iter_mut() {
+            if active.timer.tick(time.delta()).just_finished() {
+                writer.send(EffectExpiredEvent { entity, effect: active.effect });
             }
         }
     }
 }
 
+fn expire_status_effects(
+    mut commands: Commands,
+    mut reader: EventReader<EffectExpiredEvent>,
+    mut query: Query<&mut StatusEffects>,
+) {
+    for event in reader.iter() {
+        let Ok(mut effects) = query.get_mut(event.entity) else { continue };
+        remove_expired(&mut effects, event.effect);
+        if effects.0.is_empty() {
+            commands.entity(event.entity).remove::<StatusEffects>();
+        }
+    }
+}
+
+/// Drops `effect` if its timer has run out. An effect picked up again since
+/// it ran out keeps going.
+fn remove_expired(effects: &mut StatusEffects, effect: StatusEffect) {
+    effects.0.retain(|active| active.effect != effect || !active.timer.finished());
+}
+
 fn collide_hostile(
     hostile_query: Query<&Transform, With<Hostile>>,
     mut cell_query: Query<(&Transform, &mut Lifespan), With<Cell>>,
@@ -358,29 +573,54 @@ fn has_collided(a: &Transform, b: &Transform) -> bool {
     ).is_some()
 }
 
-fn _get_random_translation(
-    wall_query: &Query<&Transform, (With<Wall>, Without<Cell>)>,
-    cell_query: &Query<&Transform, (With<Cell>, Without<Wall>)>,
-) -> Vec3 {
-    let mut width = _get_random_position(SCREEN_WIDTH);
-    let mut height = _get_random_position(SCREEN_HEIGHT);
-    let new_transform = Transform {
-        translation: Vec3::new(width, height, 0.0),
-        scale: Vec3::new(CELL_SIZE, CELL_SIZE, 0.0),
-        ..default()
-    };
-    while cell_query.iter()
-        .any(|cell_transform| has_collided(cell_transform, &new_transform))
-        && wall_query.iter()
-            .any(|wall_transform| has_collided(wall_transform, &new_transform))
-    {
-        width = _get_random_position(SCREEN_WIDTH);
-        height = _get_random_position(SCREEN_HEIGHT);
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    fn get_speed(effects: &StatusEffects) -> &ActiveEffect {
+        effects.0.iter().find(|active| active.effect == StatusEffect::Speed).unwrap()
     }
-    Vec3::new(width, height, 0.0)
-}
 
-fn _get_random_position(size: f32) -> f32 {
-    (random::<f32>() * (size - CELL_SIZE)) - (size / 2.0 - (CELL_SIZE / 2.0))
-}
+    #[test]
+    fn stacks_stop_at_the_limit() {
+        let (_, max_stacks) = get_effect_rules(StatusEffect::Speed);
+        let mut effects = StatusEffects::default();
+        for _ in 0..max_stacks + 2 {
+            add_status_effect(&mut effects, StatusEffect::Speed);
+        }
+        assert_eq!(effects.0.len(), 1);
+        assert_eq!(get_stacks(&effects, StatusEffect::Speed), max_stacks);
+    }
+
+    #[test]
+    fn pickup_at_the_limit_restarts_the_timer() {
+        let (duration, max_stacks) = get_effect_rules(StatusEffect::Speed);
+        let mut effects = StatusEffects::default();
+        for _ in 0..max_stacks {
+            add_status_effect(&mut effects, StatusEffect::Speed);
+        }
+        effects.0[0].timer.tick(Duration::from_secs_f32(duration / 2.0));
+        add_status_effect(&mut effects, StatusEffect::Speed);
+        assert_eq!(get_stacks(&effects, StatusEffect::Speed), max_stacks);
+        assert_eq!(get_speed(&effects).timer.elapsed(), Duration::ZERO);
+    }
 
+    #[test]
+    fn expired_effect_is_removed_unless_picked_up_again() {
+        let (duration, _) = get_effect_rules(StatusEffect::Speed);
+        let mut effects = StatusEffects::default();
+        add_status_effect(&mut effects, StatusEffect::Speed);
+        effects.0[0].timer.tick(Duration::from_secs_f32(duration));
+
+        // Picked up again before the expiry event is handled.
+        let mut picked_up = StatusEffects(effects.0.clone());
+        add_status_effect(&mut picked_up, StatusEffect::Speed);
+        remove_expired(&mut picked_up, StatusEffect::Speed);
+        assert_eq!(get_stacks(&picked_up, StatusEffect::Speed), 1);
+        assert!(!get_speed(&picked_up).timer.finished());
+
+        remove_expired(&mut effects, StatusEffect::Speed);
+        assert!(effects.0.is_empty());
+    }
+}
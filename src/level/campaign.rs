@@ -0,0 +1,213 @@
+use std::{error::Error, fmt};
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture};
+use serde::Deserialize;
+use super::loader::LevelAsset;
+
+/// Relative to the assets folder.
+pub const CAMPAIGN_PATH: &str = "main.campaign.ron";
+
+/// The ordered list of levels in `assets/main.campaign.ron`.
+#[derive(Deserialize, Clone, Debug, TypeUuid)]
+#[uuid = "3c1f5a9e-8d2b-4e67-a0f4-5b9c7d1e2a38"]
+pub struct CampaignManifest {
+    pub levels: Vec<CampaignLevel>,
+}
+
+impl CampaignManifest {
+    /// Every level must be reachable: there is at least one, and each
+    /// `Unlock::Cleared` names a level that comes before it.
+    pub fn validate(&self) -> Result<(), CampaignError> {
+        if self.levels.is_empty() {
+            return Err(CampaignError::NoLevels);
+        }
+        for (index, level) in self.levels.iter().enumerate() {
+            if let Unlock::Cleared(cleared) = level.unlock {
+                if cleared >= index {
+                    return Err(CampaignError::LaterUnlock { name: level.name.clone(), cleared });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CampaignError {
+    NoLevels,
+    /// A level is unlocked by clearing itself or a level after it.
+    LaterUnlock { name: String, cleared: usize },
+}
+
+impl fmt::Display for CampaignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CampaignError::NoLevels => write!(f, "the campaign has no levels"),
+            CampaignError::LaterUnlock { name, cleared } => write!(f,
+                "'{name}' is unlocked by clearing level {cleared}, which does not come before it"),
+        }
+    }
+}
+
+impl Error for CampaignError {}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct CampaignLevel {
+    pub name: String,
+    /// Relative to the assets folder.
+    pub path: String,
+    /// Seconds a good run should take to clear the level.
+    pub par_time: f32,
+    #[serde(default)]
+    pub unlock: Unlock,
+}
+
+/// What it takes for a level to be played rather than skipped.
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub enum Unlock {
+    #[default]
+    Always,
+    /// The total score so far is at least this much.
+    Score(u32),
+    /// The level at this index has been cleared.
+    Cleared(usize),
+}
+
+#[derive(Default)]
+pub struct CampaignLoader;
+
+impl AssetLoader for CampaignLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let manifest: CampaignManifest = ron::de::from_bytes(bytes)?;
+            manifest.validate()?;
+            load_context.set_default_asset(LoadedAsset::new(manifest));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["campaign.ron"]
+    }
+}
+
+#[derive(Resource)]
+pub struct Campaign {
+    pub levels: Vec<CampaignLevel>,
+    pub handles: Vec<Handle<LevelAsset>>,
+    /// Index into `levels` of the level being played.
+    pub index: usize,
+    /// Fastest clear of each level, in seconds.
+    pub best_times: Vec<Option<f32>>,
+    /// Seconds spent in the current level so far.
+    pub time: f32,
+}
+
+impl Campaign {
+    pub fn new(manifest: &CampaignManifest, asset_server: &AssetServer) -> Campaign {
+        let handles = manifest.levels.iter()
+            .map(|level| asset_server.load(level.path.as_str()))
+            .collect();
+        Campaign {
+            best_times: vec![None; manifest.levels.len()],
+            levels: manifest.levels.clone(),
+            handles,
+            index: 0,
+            time: 0.0,
+        }
+    }
+
+    pub fn current(&self) -> &CampaignLevel {
+        &self.levels[self.index]
+    }
+
+    pub fn clear_current(&mut self) {
+        let best_time = self.best_times[self.index].map_or(self.time, |best| best.min(self.time));
+        self.best_times[self.index] = Some(best_time);
+    }
+
+    pub fn is_unlocked(&self, index: usize, score: u32) -> bool {
+        match self.levels[index].unlock {
+            Unlock::Always => true,
+            Unlock::Score(required) => score >= required,
+            Unlock::Cleared(level) => matches!(self.best_times.get(level), Some(Some(_))),
+        }
+    }
+
+    /// The first unlocked level after the current one, if any.
+    pub fn next(&self, score: u32) -> Option<usize> {
+        (self.index + 1..self.levels.len()).find(|index| self.is_unlocked(*index, score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_level(unlock: Unlock) -> CampaignLevel {
+        CampaignLevel { name: "level".to_string(), path: String::new(), par_time: 10.0, unlock }
+    }
+
+    fn get_campaign(unlocks: &[Unlock]) -> Campaign {
+        Campaign {
+            levels: unlocks.iter().map(|unlock| get_level(*unlock)).collect(),
+            handles: Vec::new(),
+            index: 0,
+            best_times: vec![None; unlocks.len()],
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn levels_unlock_by_score_or_clearing() {
+        let mut campaign = get_campaign(&[Unlock::Always, Unlock::Score(100), Unlock::Cleared(0)]);
+        assert!(campaign.is_unlocked(0, 0));
+        assert!(!campaign.is_unlocked(1, 99));
+        assert!(campaign.is_unlocked(1, 100));
+        assert!(!campaign.is_unlocked(2, 1000));
+        campaign.clear_current();
+        assert!(campaign.is_unlocked(2, 0));
+    }
+
+    #[test]
+    fn next_skips_locked_levels() {
+        let mut campaign = get_campaign(&[
+            Unlock::Always, Unlock::Score(100), Unlock::Cleared(1), Unlock::Always]);
+        assert_eq!(campaign.next(100), Some(1));
+        assert_eq!(campaign.next(0), Some(3));
+        campaign.index = 3;
+        assert_eq!(campaign.next(100), None);
+    }
+
+    #[test]
+    fn clearing_keeps_the_best_time() {
+        let mut campaign = get_campaign(&[Unlock::Always]);
+        campaign.time = 12.0;
+        campaign.clear_current();
+        campaign.time = 15.0;
+        campaign.clear_current();
+        assert_eq!(campaign.best_times, [Some(12.0)]);
+    }
+
+    #[test]
+    fn unreachable_campaigns_are_rejected() {
+        let manifest = |unlocks: &[Unlock]| CampaignManifest {
+            levels: unlocks.iter().map(|unlock| get_level(*unlock)).collect(),
+        };
+        assert_eq!(manifest(&[]).validate(), Err(CampaignError::NoLevels));
+        assert_eq!(manifest(&[Unlock::Always, Unlock::Cleared(0)]).validate(), Ok(()));
+        assert_eq!(
+            manifest(&[Unlock::Always, Unlock::Cleared(1)]).validate(),
+            Err(CampaignError::LaterUnlock { name: "level".to_string(), cleared: 1 }));
+        assert_eq!(
+            manifest(&[Unlock::Cleared(5)]).validate(),
+            Err(CampaignError::LaterUnlock { name: "level".to_string(), cleared: 5 }));
+    }
+}
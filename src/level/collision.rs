@@ -0,0 +1,387 @@
+use bevy::prelude::*;
+use super::grid::{LevelGrid, TILE_SIZE, Tile};
+
+/// Bounces allowed in one move before the box stops for the rest of it,
+/// so a box wedged between walls cannot loop forever.
+const MAX_BOUNCES: usize = 4;
+/// Entry times closer than this count as the same contact.
+const TIME_EPSILON: f32 = 1e-5;
+/// Distance a box is kept off a surface it bounced from.
+const SKIN: f32 = 1e-3;
+
+/// The first contact of a box swept through the grid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    /// Fraction of the motion covered before the contact, from 0 to 1.
+    pub time: f32,
+    /// Unit axes of the surfaces touched: one axis for a face, both for a
+    /// corner or two faces touched at once.
+    pub normal: Vec2,
+}
+
+/// Where a box ends up after `move_bouncing`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounced {
+    pub translation: Vec2,
+    pub velocity: Vec2,
+    pub bounces: usize,
+}
+
+/// Sweeps a box of `size` centered on `translation` along `motion` and
+/// returns its first contact with a tile for which `is_solid` holds. Faces
+/// shared by two solid tiles cannot be hit, so a box never catches on the
+/// seams of a flat wall. A box already inside a tile can move out of it.
+pub fn sweep(
+    grid: &LevelGrid,
+    translation: Vec2,
+    size: Vec2,
+    motion: Vec2,
+    is_solid: impl Fn(Tile) -> bool,
+) -> Option<Hit> {
+    let start = translation;
+    let end = translation + motion;
+    let bounds_min = start.min(end) - size / 2.0;
+    let bounds_max = start.max(end) + size / 2.0;
+    let is_solid_at = |col: isize, row: isize| {
+        col >= 0 && row >= 0 && matches!(grid.get(col as usize, row as usize), Some(tile) if is_solid(tile))
+    };
+    let mut first: Option<Hit> = None;
+    let tiles = grid.overlapping_tiles(
+        ((bounds_min + bounds_max) / 2.0).extend(0.0), bounds_max - bounds_min);
+    for (col, row, tile) in tiles {
+        if !is_solid(tile) {
+            continue;
+        }
+        let center = grid.tile_to_world(col, row).truncate();
+        let Some(mut hit) = sweep_tile(center, translation, size, motion) else { continue };
+        // The neighbor across a face is hit instead, at the same time or sooner.
+        let (col, row) = (col as isize, row as isize);
+        if hit.normal.x != 0.0 && is_solid_at(col + hit.normal.x as isize, row) {
+            hit.normal.x = 0.0;
+        }
+        if hit.normal.y != 0.0 && is_solid_at(col, row - hit.normal.y as isize) {
+            hit.normal.y = 0.0;
+        }
+        if hit.normal == Vec2::ZERO {
+            continue;
+        }
+        first = match first {
+            Some(first) if first.time < hit.time - TIME_EPSILON => Some(first),
+            Some(first) if first.time <= hit.time + TIME_EPSILON => {
+                let normal = Vec2::select(first.normal.cmpne(Vec2::ZERO), first.normal, hit.normal);
+                Some(Hit { time: first.time.min(hit.time), normal })
+            }
+            _ => Some(hit),
+        };
+    }
+    first
+}
+
+/// Moves a box by `velocity * time`, reflecting it off solid tiles along
+/// the way. Each surface flips its axis of the velocity once. A box that
+/// starts inside solid tiles is first pushed out of them, which counts as a
+/// bounce off the faces it is pushed across.
+pub fn move_bouncing(
+    grid: &LevelGrid,
+    translation: Vec2,
+    size: Vec2,
+    velocity: Vec2,
+    time: f32,
+    is_solid: impl Fn(Tile) -> bool,
+) -> Bounced {
+    let (translation, normal) = push_out(grid, translation, size, &is_solid);
+    let mut bounced = Bounced { translation, velocity, bounces: 0 };
+    if normal != Vec2::ZERO {
+        bounced.velocity = reflect(bounced.velocity, normal);
+        bounced.bounces += 1;
+    }
+    let mut remaining = time;
+    while bounced.bounces < MAX_BOUNCES {
+        let motion = bounced.velocity * remaining;
+        let Some(hit) = sweep(grid, bounced.translation, size, motion, &is_solid) else {
+            bounced.translation += motion;
+            break;
+        };
+        bounced.translation += motion * hit.time + hit.normal * SKIN;
+        bounced.velocity = reflect(bounced.velocity, hit.normal);
+        bounced.bounces += 1;
+        remaining *= 1.0 - hit.time;
+    }
+    bounced
+}
+
+/// Fraction of `motion` after which a box stops or starts overlapping tiles
+/// for which `is_inside` holds, or `None` if it never does. Moving from one
+/// such tile straight into its neighbor is not a boundary, and neither is
+/// one within `SKIN` of the start, so a boundary just crossed is not found
+/// again.
+pub fn sweep_boundary(
+    grid: &LevelGrid,
+    translation: Vec2,
+    size: Vec2,
+    motion: Vec2,
+    is_inside: impl Fn(Tile) -> bool,
+) -> Option<f32> {
+    let end = translation + motion;
+    let bounds_min = translation.min(end) - size / 2.0;
+    let bounds_max = translation.max(end) + size / 2.0;
+    let overlaps: Vec<(f32, f32)> = grid.overlapping_tiles(
+            ((bounds_min + bounds_max) / 2.0).extend(0.0), bounds_max - bounds_min)
+        .filter(|(_, _, tile)| is_inside(*tile))
+        .filter_map(|(col, row, _)| {
+            let center = grid.tile_to_world(col, row).truncate();
+            get_overlap_times(center, translation, size, motion)
+        })
+        .collect();
+    // Whether the box overlaps any of them just after `time`.
+    let is_inside_after = |time: f32| overlaps.iter().any(|&(entry, exit)| entry <= time && time < exit);
+    let was_inside = is_inside_after(0.0);
+    let mut times: Vec<f32> = overlaps.iter()
+        .flat_map(|&(entry, exit)| [entry, exit])
+        .filter(|time| *time * motion.length() > SKIN && *time < 1.0)
+        .collect();
+    times.sort_by(f32::total_cmp);
+    times.into_iter().find(|time| is_inside_after(*time) != was_inside)
+}
+
+/// Moves a box out of the solid tiles it overlaps, each time across the
+/// shallowest face not shared with another solid tile, and returns where it
+/// ends up with the normals of the faces it crossed. A box buried too deep
+/// to reach an open face is left where it is.
+fn push_out(
+    grid: &LevelGrid,
+    mut translation: Vec2,
+    size: Vec2,
+    is_solid: impl Fn(Tile) -> bool,
+) -> (Vec2, Vec2) {
+    let is_solid_at = |col: isize, row: isize| {
+        col >= 0 && row >= 0 && matches!(grid.get(col as usize, row as usize), Some(tile) if is_solid(tile))
+    };
+    let half = (size + Vec2::splat(TILE_SIZE)) / 2.0;
+    let mut normal = Vec2::ZERO;
+    for _ in 0..MAX_BOUNCES {
+        let mut shallowest: Option<(f32, Vec2)> = None;
+        for (col, row, tile) in grid.overlapping_tiles(translation.extend(0.0), size) {
+            let offset = translation - grid.tile_to_world(col, row).truncate();
+            if !is_solid(tile) || offset.x.abs() >= half.x || offset.y.abs() >= half.y {
+                continue;
+            }
+            let (col, row) = (col as isize, row as isize);
+            let faces = [
+                (half.x - offset.x, Vec2::X, (col + 1, row)),
+                (half.x + offset.x, Vec2::NEG_X, (col - 1, row)),
+                (half.y - offset.y, Vec2::Y, (col, row - 1)),
+                (half.y + offset.y, Vec2::NEG_Y, (col, row + 1)),
+            ];
+            for (depth, face, (col, row)) in faces {
+                if is_solid_at(col, row) || shallowest.is_some_and(|(least, _)| least <= depth) {
+                    continue;
+                }
+                shallowest = Some((depth, face));
+            }
+        }
+        let Some((depth, face)) = shallowest else { break };
+        translation += face * (depth + SKIN);
+        normal = Vec2::select(face.cmpne(Vec2::ZERO), face, normal);
+    }
+    (translation, normal)
+}
+
+/// Flips each axis of `velocity` that has a normal to point along it.
+fn reflect(mut velocity: Vec2, normal: Vec2) -> Vec2 {
+    if normal.x != 0.0 {
+        velocity.x = normal.x * velocity.x.abs();
+    }
+    if normal.y != 0.0 {
+        velocity.y = normal.y * velocity.y.abs();
+    }
+    velocity
+}
+
+/// Swept box against one tile, as a point against the tile grown by the
+/// box's half size. The normal is the axis entered last.
+fn sweep_tile(center: Vec2, translation: Vec2, size: Vec2, motion: Vec2) -> Option<Hit> {
+    let half = (size + Vec2::splat(TILE_SIZE)) / 2.0;
+    let (entry_x, exit_x) = get_axis_times(center.x - half.x, center.x + half.x, translation.x, motion.x)?;
+    let (entry_y, exit_y) = get_axis_times(center.y - half.y, center.y + half.y, translation.y, motion.y)?;
+    let entry = entry_x.max(entry_y);
+    let exit = exit_x.min(exit_y);
+    if entry >= exit || !(0.0..=1.0).contains(&entry) {
+        return None;
+    }
+    let mut normal = Vec2::ZERO;
+    if entry_x >= entry - TIME_EPSILON {
+        normal.x = -motion.x.signum();
+    }
+    if entry_y >= entry - TIME_EPSILON {
+        normal.y = -motion.y.signum();
+    }
+    Some(Hit { time: entry, normal })
+}
+
+/// Times at which a box moving by `motion` starts and stops overlapping one
+/// tile, unbounded on the sides it never crosses.
+fn get_overlap_times(center: Vec2, translation: Vec2, size: Vec2, motion: Vec2) -> Option<(f32, f32)> {
+    let half = (size + Vec2::splat(TILE_SIZE)) / 2.0;
+    let (entry_x, exit_x) = get_axis_times(center.x - half.x, center.x + half.x, translation.x, motion.x)?;
+    let (entry_y, exit_y) = get_axis_times(center.y - half.y, center.y + half.y, translation.y, motion.y)?;
+    let entry = entry_x.max(entry_y);
+    let exit = exit_x.min(exit_y);
+    (entry < exit).then_some((entry, exit))
+}
+
+/// Times at which a point moving by `motion` enters and leaves the open
+/// interval `(min, max)`, or `None` if it never is inside it.
+fn get_axis_times(min: f32, max: f32, position: f32, motion: f32) -> Option<(f32, f32)> {
+    if motion == 0.0 {
+        return (min < position && position < max).then_some((f32::NEG_INFINITY, f32::INFINITY));
+    }
+    let to_min = (min - position) / motion;
+    let to_max = (max - position) / motion;
+    Some((to_min.min(to_max), to_min.max(to_max)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: Vec2 = Vec2::new(2.0, 2.0);
+
+    /// A 7x7 room of `Tile::Wall`, with extra walls at `walls`.
+    fn get_room(walls: &[(usize, usize)]) -> LevelGrid {
+        let mut grid = LevelGrid::filled(7, 7, Tile::Floor);
+        for i in 0..7 {
+            for (col, row) in [(i, 0), (i, 6), (0, i), (6, i)] {
+                grid.set(col, row, Tile::Wall);
+            }
+        }
+        for &(col, row) in walls {
+            grid.set(col, row, Tile::Wall);
+        }
+        grid
+    }
+
+    fn move_in(grid: &LevelGrid, translation: Vec2, velocity: Vec2, time: f32) -> Bounced {
+        move_bouncing(grid, translation, SIZE, velocity, time, |tile| tile == Tile::Wall)
+    }
+
+    #[test]
+    fn head_on_hit_stops_at_the_face_and_reflects() {
+        let grid = get_room(&[]);
+        let hit = sweep(&grid, Vec2::ZERO, SIZE, Vec2::new(100.0, 0.0), |tile| tile == Tile::Wall);
+        // The right wall's face is at 2.5 tiles, the box's half size is 1.
+        let face = 2.5 * TILE_SIZE - 1.0;
+        assert_eq!(hit, Some(Hit { time: face / 100.0, normal: Vec2::new(-1.0, 0.0) }));
+        let bounced = move_in(&grid, Vec2::ZERO, Vec2::new(100.0, 0.0), 1.0);
+        assert_eq!(bounced.velocity, Vec2::new(-100.0, 0.0));
+        assert_eq!(bounced.bounces, 1);
+        assert!((bounced.translation.x - (face - (100.0 - face))).abs() < 0.01);
+    }
+
+    #[test]
+    fn glancing_hit_on_a_flat_wall_flips_only_its_axis() {
+        let grid = get_room(&[]);
+        // Crosses the seams between the bottom wall's tiles while falling onto it.
+        let bounced = move_in(&grid, Vec2::new(-40.0, 0.0), Vec2::new(60.0, -60.0), 1.0);
+        assert_eq!(bounced.velocity, Vec2::new(60.0, 60.0));
+        assert_eq!(bounced.bounces, 1);
+    }
+
+    #[test]
+    fn sliding_along_a_wall_does_not_catch_on_seams() {
+        let grid = get_room(&[]);
+        let bottom_face = -2.5 * TILE_SIZE + 1.0;
+        let bounced = move_in(&grid, Vec2::new(-40.0, bottom_face), Vec2::new(60.0, 0.0), 1.0);
+        assert_eq!(bounced.velocity, Vec2::new(60.0, 0.0));
+        assert_eq!(bounced.bounces, 0);
+        assert_eq!(bounced.translation, Vec2::new(20.0, bottom_face));
+    }
+
+    #[test]
+    fn inside_corner_flips_both_axes_once() {
+        let grid = get_room(&[]);
+        let corner = 2.5 * TILE_SIZE - 1.0;
+        let start = Vec2::splat(corner - 10.0);
+        let hit = sweep(&grid, start, SIZE, Vec2::splat(20.0), |tile| tile == Tile::Wall);
+        assert_eq!(hit, Some(Hit { time: 0.5, normal: Vec2::new(-1.0, -1.0) }));
+        let bounced = move_in(&grid, start, Vec2::splat(20.0), 1.0);
+        assert_eq!(bounced.velocity, Vec2::splat(-20.0));
+        assert_eq!(bounced.bounces, 1);
+    }
+
+    #[test]
+    fn outside_corner_reflects_back() {
+        // A lone pillar in the middle of the room.
+        let grid = get_room(&[(3, 3)]);
+        let corner = TILE_SIZE / 2.0 + 1.0;
+        let start = Vec2::splat(-corner - 10.0);
+        let hit = sweep(&grid, start, SIZE, Vec2::splat(20.0), |tile| tile == Tile::Wall);
+        assert_eq!(hit, Some(Hit { time: 0.5, normal: Vec2::new(-1.0, -1.0) }));
+    }
+
+    #[test]
+    fn fast_box_does_not_tunnel_through_a_thin_wall() {
+        // A one-tile wall across the middle column.
+        let grid = get_room(&[(3, 1), (3, 2), (3, 3), (3, 4), (3, 5)]);
+        let start = Vec2::new(-2.0 * TILE_SIZE, 0.0);
+        let bounced = move_in(&grid, start, Vec2::new(10_000.0, 0.0), 1.0);
+        assert!(bounced.translation.x < -TILE_SIZE / 2.0);
+        assert!(bounced.bounces > 0);
+    }
+
+    #[test]
+    fn bounces_several_times_in_one_move() {
+        let grid = get_room(&[]);
+        let width = 5.0 * TILE_SIZE - SIZE.x;
+        // Right wall, left wall, then back to the middle.
+        let bounced = move_in(&grid, Vec2::ZERO, Vec2::new(2.0 * width, 0.0), 1.0);
+        assert_eq!(bounced.bounces, 2);
+        assert!(bounced.translation.x.abs() < 0.05);
+        assert_eq!(bounced.velocity.x, 2.0 * width);
+    }
+
+    #[test]
+    fn touching_a_wall_and_moving_away_is_not_a_hit() {
+        let grid = get_room(&[]);
+        let face = 2.5 * TILE_SIZE - 1.0;
+        let hit = sweep(&grid, Vec2::new(face, 0.0), SIZE, Vec2::new(-10.0, 0.0), |tile| tile == Tile::Wall);
+        assert_eq!(hit, None);
+    }
+
+    #[test]
+    fn box_stops_after_too_many_bounces() {
+        let grid = get_room(&[]);
+        let bounced = move_in(&grid, Vec2::ZERO, Vec2::new(1e6, 0.0), 1.0);
+        assert_eq!(bounced.bounces, MAX_BOUNCES);
+        assert!(bounced.translation.x.abs() < 2.5 * TILE_SIZE);
+    }
+
+    #[test]
+    fn box_inside_a_wall_is_pushed_out_and_bounced() {
+        // A one-tile wall across the middle column, with the box just inside its left face.
+        let grid = get_room(&[(3, 1), (3, 2), (3, 3), (3, 4), (3, 5)]);
+        let start = Vec2::new(-TILE_SIZE / 2.0 + 0.5, 0.0);
+        let bounced = move_in(&grid, start, Vec2::new(10.0, 0.0), 1.0);
+        assert_eq!(bounced.velocity, Vec2::new(-10.0, 0.0));
+        assert_eq!(bounced.bounces, 1);
+        assert!((bounced.translation.x - (-TILE_SIZE / 2.0 - 1.0 - 10.0)).abs() < 0.01);
+    }
+
+    #[test]
+    fn membrane_boundaries_are_where_the_box_enters_and_leaves() {
+        // Two membrane columns, from -0.5 to 1.5 tiles.
+        let mut grid = get_room(&[]);
+        for row in 1..6 {
+            grid.set(3, row, Tile::Membrane);
+            grid.set(4, row, Tile::Membrane);
+        }
+        let is_membrane = |tile| tile == Tile::Membrane;
+        let motion = Vec2::new(4.0 * TILE_SIZE, 0.0);
+        let entry = sweep_boundary(&grid, Vec2::new(-2.0 * TILE_SIZE, 0.0), SIZE, motion, is_membrane);
+        assert_eq!(entry, Some((1.5 * TILE_SIZE - 1.0) / motion.x));
+        // The seam between the columns at 0.5 tiles is not a boundary.
+        let exit = sweep_boundary(&grid, Vec2::ZERO, SIZE, motion, is_membrane);
+        assert_eq!(exit, Some((1.5 * TILE_SIZE + 1.0) / motion.x));
+        assert_eq!(sweep_boundary(&grid, Vec2::ZERO, SIZE, motion / 10.0, is_membrane), None);
+    }
+}
@@ -0,0 +1,89 @@
+use chrono::{Datelike, Local, NaiveDate};
+
+/// Days from 0001-01-01, chrono's day one, to 1970-01-01.
+const UNIX_EPOCH_DAYS: i64 = 719_163;
+
+/// Spreads consecutive days far apart, so that adding a level index to one
+/// day's seed never lands on another day's levels.
+pub fn get_daily_seed(day: i64) -> u64 {
+    (day as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+/// Days since 1970-01-01 in the local time zone.
+pub fn get_local_day() -> i64 {
+    i64::from(Local::now().date_naive().num_days_from_ce()) - UNIX_EPOCH_DAYS
+}
+
+/// The `year-month-day` of a day counted from 1970-01-01.
+pub fn format_day(day: i64) -> String {
+    i32::try_from(day + UNIX_EPOCH_DAYS).ok()
+        .and_then(NaiveDate::from_num_days_from_ce_opt)
+        .map_or_else(|| format!("day {day}"), |date| date.format("%Y-%m-%d").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::difficulty::Difficulty;
+    use super::super::generator::generate;
+
+    /// 2024-02-29, days since 1970-01-01.
+    const DAY: i64 = 19_782;
+
+    /// The first level of that day's run. Changing it changes every past
+    /// daily run, so scores can no longer be compared.
+    const DAILY_LEVEL: &str = "\
+fire_rate = 0.4\n\
+E1: health=1\n\
+---\n\
+|||||||||||||||||||||\n\
+|..E................|\n\
+|...................|\n\
+|...................|\n\
+|...................|\n\
+|......|............|\n\
+|......|............|\n\
+|......|............|\n\
+|......|............|\n\
+|......|....*.......|\n\
+|......|............|\n\
+|......|............|\n\
+|......|............|\n\
+|......|............|\n\
+|...................|\n\
+|....||||||||||.....|\n\
+|......*............|\n\
+|.................P.|\n\
+||.........||||||||.|\n\
+||..................|\n\
+|||||||||||||||||||||\n\
+";
+
+    #[test]
+    fn daily_seed_is_pinned() {
+        assert_eq!(get_daily_seed(DAY), 0xf2c8_1402_35b0_3ebe);
+        assert_eq!(get_daily_seed(0), 0);
+    }
+
+    #[test]
+    fn days_format_as_dates() {
+        assert_eq!(format_day(0), "1970-01-01");
+        assert_eq!(format_day(DAY), "2024-02-29");
+        assert_eq!(format_day(-1), "1969-12-31");
+        assert_eq!(format_day(i64::MAX / 2), format!("day {}", i64::MAX / 2));
+    }
+
+    #[test]
+    fn daily_level_is_pinned() {
+        let difficulty = Difficulty {
+            level: 0,
+            wall_segments: 4,
+            enemies: 1,
+            enemy_health: 1,
+            fire_rate: 0.4,
+            power_ups: 2,
+        };
+        let grid = generate(get_daily_seed(DAY), &difficulty).unwrap();
+        assert_eq!(grid.to_string(), DAILY_LEVEL);
+    }
+}
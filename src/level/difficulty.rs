@@ -0,0 +1,208 @@
+use std::{error::Error, fmt};
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture};
+use serde::Deserialize;
+
+/// Relative to the assets folder.
+pub const DIFFICULTY_PATH: &str = "main.difficulty.ron";
+
+/// Upper bounds on a key, past which levels stop fitting the generator's
+/// grid or stop being playable.
+const MAX_WALL_SEGMENTS: usize = 30;
+const MAX_ENEMIES: usize = 20;
+const MAX_ENEMY_HEALTH: i32 = 10;
+const MAX_FIRE_RATE: f32 = 4.0;
+const MAX_POWER_UPS: usize = 10;
+
+/// Generator settings for one level of a generated sequence.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Difficulty {
+    /// Index of the level in the sequence, starting at 0.
+    pub level: usize,
+    pub wall_segments: usize,
+    pub enemies: usize,
+    pub enemy_health: i32,
+    /// Shots per second fired by each enemy.
+    pub fire_rate: f32,
+    /// `StatusEffect` pickups.
+    pub power_ups: usize,
+}
+
+/// Key levels from `assets/main.difficulty.ron`. Levels between two keys
+/// are interpolated and levels past the last key stay at the last key.
+#[derive(Resource, Deserialize, Clone, Debug, TypeUuid)]
+#[uuid = "8e4b2d71-5c3a-4f90-b6e8-2a7d9c1f0e54"]
+pub struct DifficultyCurve {
+    pub keys: Vec<Difficulty>,
+}
+
+impl DifficultyCurve {
+    pub fn get(&self, level: usize) -> Difficulty {
+        let next = self.keys.iter().position(|key| key.level > level);
+        let (from, to) = match next {
+            Some(0) => return Difficulty { level, ..self.keys[0] },
+            Some(next) => (self.keys[next - 1], self.keys[next]),
+            None => return Difficulty { level, ..self.keys[self.keys.len() - 1] },
+        };
+        let t = (level - from.level) as f32 / (to.level - from.level) as f32;
+        let lerp = |from: f32, to: f32| from + (to - from) * t;
+        Difficulty {
+            level,
+            wall_segments: lerp(from.wall_segments as f32, to.wall_segments as f32).round() as usize,
+            enemies: lerp(from.enemies as f32, to.enemies as f32).round() as usize,
+            enemy_health: lerp(from.enemy_health as f32, to.enemy_health as f32).round() as i32,
+            fire_rate: lerp(from.fire_rate, to.fire_rate),
+            power_ups: lerp(from.power_ups as f32, to.power_ups as f32).round() as usize,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DifficultyError {
+    Ron(ron::error::SpannedError),
+    NoKeys,
+    /// A key is not at a later level than the one before it.
+    Unordered { level: usize },
+    /// A key without enemies, or with enemies that cannot hurt the player.
+    Unplayable { level: usize },
+    OverLimits { level: usize },
+}
+
+impl fmt::Display for DifficultyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DifficultyError::Ron(error) => write!(f, "{error}"),
+            DifficultyError::NoKeys => write!(f, "difficulty curve has no keys"),
+            DifficultyError::Unordered { level } => write!(f,
+                "difficulty curve key for level {level} is not after the key before it"),
+            DifficultyError::Unplayable { level } => write!(f,
+                "difficulty curve key for level {level} needs enemies, health and a fire rate"),
+            DifficultyError::OverLimits { level } => write!(f,
+                "difficulty curve key for level {level} is over the limits"),
+        }
+    }
+}
+
+impl Error for DifficultyError {}
+
+impl From<ron::error::SpannedError> for DifficultyError {
+    fn from(error: ron::error::SpannedError) -> DifficultyError {
+        DifficultyError::Ron(error)
+    }
+}
+
+pub fn parse_difficulty_curve(source: &[u8]) -> Result<DifficultyCurve, DifficultyError> {
+    let curve: DifficultyCurve = ron::de::from_bytes(source)?;
+    if curve.keys.is_empty() {
+        return Err(DifficultyError::NoKeys);
+    }
+    if let Some(keys) = curve.keys.windows(2).find(|keys| keys[0].level >= keys[1].level) {
+        return Err(DifficultyError::Unordered { level: keys[1].level });
+    }
+    let is_unplayable = |key: &Difficulty| {
+        key.enemies == 0 || key.enemy_health <= 0 || key.fire_rate <= 0.0
+    };
+    if let Some(key) = curve.keys.iter().find(|key| is_unplayable(key)) {
+        return Err(DifficultyError::Unplayable { level: key.level });
+    }
+    let is_too_hard = |key: &Difficulty| {
+        key.wall_segments > MAX_WALL_SEGMENTS
+            || key.enemies > MAX_ENEMIES
+            || key.enemy_health > MAX_ENEMY_HEALTH
+            || key.fire_rate > MAX_FIRE_RATE
+            || key.power_ups > MAX_POWER_UPS
+    };
+    if let Some(key) = curve.keys.iter().find(|key| is_too_hard(key)) {
+        return Err(DifficultyError::OverLimits { level: key.level });
+    }
+    Ok(curve)
+}
+
+#[derive(Default)]
+pub struct DifficultyCurveLoader;
+
+impl AssetLoader for DifficultyCurveLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let curve = parse_difficulty_curve(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(curve));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["difficulty.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::generator::generate;
+
+    const MAX_DIFFICULTY: Difficulty = Difficulty {
+        level: 0,
+        wall_segments: MAX_WALL_SEGMENTS,
+        enemies: MAX_ENEMIES,
+        enemy_health: MAX_ENEMY_HEALTH,
+        fire_rate: MAX_FIRE_RATE,
+        power_ups: MAX_POWER_UPS,
+    };
+
+    fn get_key_source(level: usize, enemies: usize) -> String {
+        format!("(level: {level}, wall_segments: 4, enemies: {enemies}, enemy_health: 1, \
+            fire_rate: 0.5, power_ups: 1)")
+    }
+
+    fn read_shipped_curve() -> DifficultyCurve {
+        let source = std::fs::read(format!("assets/{DIFFICULTY_PATH}")).unwrap();
+        parse_difficulty_curve(&source).unwrap()
+    }
+
+    #[test]
+    fn shipped_curve_rises_between_keys() {
+        let curve = read_shipped_curve();
+        let last = curve.keys[curve.keys.len() - 1].level;
+        for level in 0..=last {
+            let (easier, harder) = (curve.get(level), curve.get(level + 1));
+            assert!(harder.wall_segments >= easier.wall_segments, "level {level}");
+            assert!(harder.enemies >= easier.enemies, "level {level}");
+            assert!(harder.enemy_health >= easier.enemy_health, "level {level}");
+            assert!(harder.fire_rate >= easier.fire_rate, "level {level}");
+            assert!(harder.power_ups <= easier.power_ups, "level {level}");
+        }
+    }
+
+    #[test]
+    fn keys_are_hit_exactly() {
+        let curve = read_shipped_curve();
+        for key in &curve.keys {
+            assert_eq!(curve.get(key.level), *key);
+        }
+    }
+
+    #[test]
+    fn rejects_keys_over_the_limits() {
+        let source = format!("(keys: [{}, {}])", get_key_source(0, 1), get_key_source(5, 4));
+        assert!(parse_difficulty_curve(source.as_bytes()).is_ok());
+        let source = format!("(keys: [{}])", get_key_source(0, MAX_ENEMIES + 1));
+        assert!(parse_difficulty_curve(source.as_bytes()).is_err());
+        let source = format!("(keys: [{}, {}])", get_key_source(3, 1), get_key_source(1, 1));
+        assert!(parse_difficulty_curve(source.as_bytes()).is_err());
+        assert!(parse_difficulty_curve(b"(keys: [])").is_err());
+    }
+
+    #[test]
+    fn hardest_allowed_key_still_generates() {
+        for seed in 0..50 {
+            assert!(generate(seed, &MAX_DIFFICULTY).is_ok(), "seed {seed}");
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::{error::Error, fmt};
+use super::difficulty::Difficulty;
+use super::grid::{LevelGrid, Tile, TileProperties};
+
+const WIDTH: usize = 21;
+const HEIGHT: usize = 21;
+const MIN_OPEN_TILES: usize = 100;
+/// Layouts tried before giving up on a difficulty.
+const MAX_ATTEMPTS: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenerateError {
+    pub enemies: usize,
+    pub power_ups: usize,
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no room for {} enemies and {} power-ups after {MAX_ATTEMPTS} layouts",
+            self.enemies, self.power_ups)
+    }
+}
+
+impl Error for GenerateError {}
+
+/// The same seed and difficulty always produce the same grid, on every
+/// platform and with every version of `rand`.
+pub fn generate(seed: u64, difficulty: &Difficulty) -> Result<LevelGrid, GenerateError> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let enemies = difficulty.enemies;
+    let power_ups = difficulty.power_ups;
+    for _ in 0..MAX_ATTEMPTS {
+        let mut grid = get_bordered_grid(WIDTH, HEIGHT);
+        for _ in 0..difficulty.wall_segments {
+            add_wall_segment(&mut grid, &mut rng);
+        }
+        let floors: Vec<(usize, usize)> = grid.iter()
+            .filter(|(_, _, tile)| *tile == Tile::Floor)
+            .map(|(col, row, _)| (col, row))
+            .collect();
+        let Some(&(player_col, player_row)) = floors.choose(&mut rng) else { continue };
+        let reached = grid.flood_fill(player_col, player_row);
+        let mut open: Vec<(usize, usize)> = floors.into_iter()
+            .filter(|&(col, row)| reached[row * WIDTH + col])
+            .filter(|&tile| tile != (player_col, player_row))
+            .collect();
+        // Enemies go in the far half and power-ups in the near half.
+        let half = open.len() / 2;
+        if open.len() < MIN_OPEN_TILES || half < enemies || open.len() - half < power_ups {
+            continue;
+        }
+        // Seal off pockets the player can never reach.
+        for (i, is_reached) in reached.iter().enumerate() {
+            if !is_reached {
+                grid.tiles[i] = Tile::Wall;
+            }
+        }
+        grid.set(player_col, player_row, Tile::Player);
+        // Enemies come from the half of the map farthest from the player.
+        open.sort_by_key(|&(col, row)| {
+            std::cmp::Reverse(col.abs_diff(player_col) + row.abs_diff(player_row))
+        });
+        let (far, near) = open.split_at_mut(half);
+        far.shuffle(&mut rng);
+        for &(col, row) in &far[..enemies] {
+            grid.set(col, row, Tile::Enemy);
+        }
+        grid.header.fire_rate = Some(difficulty.fire_rate);
+        let properties = TileProperties { health: Some(difficulty.enemy_health), fire_rate: None };
+        for index in 1..=enemies {
+            grid.header.legend.insert((Tile::Enemy.glyph(), index), properties);
+        }
+        near.shuffle(&mut rng);
+        for &(col, row) in &near[..power_ups] {
+            grid.set(col, row, Tile::Speed);
+        }
+        return Ok(grid);
+    }
+    Err(GenerateError { enemies, power_ups })
+}
+
+fn get_bordered_grid(width: usize, height: usize) -> LevelGrid {
+    let mut grid = LevelGrid::filled(width, height, Tile::Floor);
+    for col in 0..width {
+        grid.set(col, 0, Tile::Wall);
+        grid.set(col, height - 1, Tile::Wall);
+    }
+    for row in 0..height {
+        grid.set(0, row, Tile::Wall);
+        grid.set(width - 1, row, Tile::Wall);
+    }
+    grid
+}
+
+fn add_wall_segment(grid: &mut LevelGrid, rng: &mut ChaCha8Rng) {
+    let length = rng.gen_range(3..=10);
+    let mut col = rng.gen_range(1..grid.width - 1);
+    let mut row = rng.gen_range(1..grid.height - 1);
+    let is_horizontal = rng.gen_bool(0.5);
+    for _ in 0..length {
+        if col >= grid.width - 1 || row >= grid.height - 1 {
+            break;
+        }
+        grid.set(col, row, Tile::Wall);
+        if is_horizontal {
+            col += 1;
+        } else {
+            row += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::difficulty::{DIFFICULTY_PATH, parse_difficulty_curve};
+
+    const DIFFICULTY: Difficulty = Difficulty {
+        level: 0,
+        wall_segments: 12,
+        enemies: 4,
+        enemy_health: 1,
+        fire_rate: 0.5,
+        power_ups: 2,
+    };
+
+    #[test]
+    fn every_enemy_and_power_up_is_reachable() {
+        for seed in 0..200 {
+            let grid = generate(seed, &DIFFICULTY).unwrap();
+            let (col, row, _) = grid.iter().find(|(_, _, tile)| *tile == Tile::Player).unwrap();
+            let reached = grid.flood_fill(col, row);
+            for (col, row, tile) in grid.iter() {
+                if matches!(tile, Tile::Enemy | Tile::Speed) {
+                    assert!(reached[row * grid.width + col],
+                        "seed {seed}: '{}' at ({col}, {row}) is unreachable", tile.glyph());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_grid() {
+        for seed in [0, 1, 42, u64::MAX] {
+            assert_eq!(generate(seed, &DIFFICULTY), generate(seed, &DIFFICULTY));
+        }
+        assert_ne!(generate(1, &DIFFICULTY), generate(2, &DIFFICULTY));
+    }
+
+    #[test]
+    fn every_curve_key_places_what_it_asks_for() {
+        let source = std::fs::read(format!("assets/{DIFFICULTY_PATH}")).unwrap();
+        let curve = parse_difficulty_curve(&source).unwrap();
+        for key in &curve.keys {
+            for seed in 0..50 {
+                let grid = generate(seed, key).unwrap();
+                let count = |tile| grid.tiles.iter().filter(|found| **found == tile).count();
+                assert_eq!(count(Tile::Enemy), key.enemies, "level {} seed {seed}", key.level);
+                assert_eq!(count(Tile::Speed), key.power_ups, "level {} seed {seed}", key.level);
+                assert_eq!(count(Tile::Player), 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use bevy::prelude::*;
+use std::{collections::BTreeMap, fmt};
+
+pub const TILE_SIZE: f32 = 24.0;
+/// Color of membrane tiles in level images and the editor.
+pub const MEMBRANE_COLOR: Color = Color::TEAL;
+/// Color of weak wall tiles in level images and the editor.
+pub const WEAK_WALL_COLOR: Color = Color::GRAY;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tile {
+    Floor,
+    /// A rigid cell wall that blocks cells and reflects particles.
+    Wall,
+    /// Blocks cells but lets particles through, slowed down.
+    Membrane,
+    /// A cell wall that explosions wear down and finally destroy.
+    WeakWall,
+    Player,
+    Enemy,
+    Speed,
+    /// A glyph from the level's declared glyph set.
+    Custom(char),
+}
+
+impl Tile {
+    pub fn from_glyph(glyph: char) -> Option<Tile> {
+        match glyph {
+            '.' => Some(Tile::Floor),
+            '|' => Some(Tile::Wall),
+            '~' => Some(Tile::Membrane),
+            '#' => Some(Tile::WeakWall),
+            'P' => Some(Tile::Player),
+            'E' => Some(Tile::Enemy),
+            '*' => Some(Tile::Speed),
+            _ => None,
+        }
+    }
+
+    pub fn glyph(self) -> char {
+        match self {
+            Tile::Floor => '.',
+            Tile::Wall => '|',
+            Tile::Membrane => '~',
+            Tile::WeakWall => '#',
+            Tile::Player => 'P',
+            Tile::Enemy => 'E',
+            Tile::Speed => '*',
+            Tile::Custom(glyph) => glyph,
+        }
+    }
+
+    /// Cell walls and membranes, which cells cannot cross.
+    pub fn is_wall(self) -> bool {
+        matches!(self, Tile::Wall | Tile::Membrane | Tile::WeakWall)
+    }
+
+    /// Cell walls, which particles bounce off.
+    pub fn blocks_particles(self) -> bool {
+        matches!(self, Tile::Wall | Tile::WeakWall)
+    }
+}
+
+/// Optional `key = value` and legend lines above a `---` separator.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LevelHeader {
+    pub name: Option<String>,
+    /// Name of a `TileRegistry` glyph set whose glyphs this level may use.
+    pub glyphs: Option<String>,
+    /// Shots per second fired by each enemy.
+    pub fire_rate: Option<f32>,
+    pub background: Option<Color>,
+    /// Looping music, relative to the assets folder.
+    pub music: Option<String>,
+    /// Properties of single tiles, keyed by glyph and the 1-based count of
+    /// that glyph in reading order, written `E1: health=3, fire_rate=0.5`.
+    pub legend: BTreeMap<(char, usize), TileProperties>,
+}
+
+/// Overrides for one spawned tile. Unset properties fall back to the level
+/// header and then to the spawner's own defaults.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TileProperties {
+    pub health: Option<i32>,
+    /// Shots per second.
+    pub fire_rate: Option<f32>,
+}
+
+impl LevelHeader {
+    pub fn is_empty(&self) -> bool {
+        *self == LevelHeader::default()
+    }
+}
+
+/// The parsed tiles of the current level, kept as a resource while in game.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct LevelGrid {
+    pub header: LevelHeader,
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<Tile>,
+}
+
+impl LevelGrid {
+    pub fn filled(width: usize, height: usize, tile: Tile) -> LevelGrid {
+        LevelGrid { 
+            header: LevelHeader::default(), 
+            width, 
+            height, 
+            tiles: vec![tile; width * height],
+        }
+    }
+
+    pub fn get(&self, col: usize, row: usize) -> Option<Tile> {
+        if col < self.width && row < self.height {
+            Some(self.tiles[row * self.width + col])
+        } else {
+            None
+        }
+    }
+
+    pub fn set(&mut self, col: usize, row: usize, tile: Tile) {
+        self.tiles[row * self.width + col] = tile;
+    }
+
+    /// Center of the tile; levels are centered on the origin with row 0 at the top.
+    pub fn tile_to_world(&self, col: usize, row: usize) -> Vec3 {
+        let x = (col as f32 - (self.width - 1) as f32 / 2.0) * TILE_SIZE;
+        let y = ((self.height - 1) as f32 / 2.0 - row as f32) * TILE_SIZE;
+        Vec3::new(x, y, 0.0)
+    }
+
+    /// The `(col, row)` of the tile containing `translation`, if inside the level.
+    pub fn world_to_tile(&self, translation: Vec3) -> Option<(usize, usize)> {
+        let col = (translation.x / TILE_SIZE + self.width as f32 / 2.0).floor();
+        let row = (self.height as f32 / 2.0 - translation.y / TILE_SIZE).floor();
+        if col < 0.0 || row < 0.0 || col >= self.width as f32 || row >= self.height as f32 {
+            return None;
+        }
+        Some((col as usize, row as usize))
+    }
+
+    /// Yields the `(col, row, tile)` of every wall or membrane tile overlapped
+    /// by a box of `size` centered on `translation`. Touching edges do not count.
+    pub fn overlapping_walls(
+        &self, 
+        translation: Vec3, 
+        size: Vec2,
+    ) -> impl Iterator<Item = (usize, usize, Tile)> + '_ {
+        self.overlapping_tiles(translation, size).filter(|(_, _, tile)| tile.is_wall())
+    }
+
+    /// Yields the `(col, row, tile)` of every tile overlapped by a box of
+    /// `size` centered on `translation`. Touching edges do not count.
+    pub fn overlapping_tiles(
+        &self, 
+        translation: Vec3, 
+        size: Vec2,
+    ) -> impl Iterator<Item = (usize, usize, Tile)> + '_ {
+        let min_col = (translation.x - size.x / 2.0) / TILE_SIZE + self.width as f32 / 2.0;
+        let max_col = (translation.x + size.x / 2.0) / TILE_SIZE + self.width as f32 / 2.0;
+        let min_row = self.height as f32 / 2.0 - (translation.y + size.y / 2.0) / TILE_SIZE;
+        let max_row = self.height as f32 / 2.0 - (translation.y - size.y / 2.0) / TILE_SIZE;
+        let cols = get_tile_span(min_col, max_col, self.width);
+        let rows = get_tile_span(min_row, max_row, self.height);
+        rows.flat_map(move |row| cols.clone().map(move |col| (col, row)))
+            .filter_map(|(col, row)| Some((col, row, self.get(col, row)?)))
+    }
+
+    /// Yields `(col, row, tile)` in reading order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, Tile)> + '_ {
+        self.tiles.iter()
+            .enumerate()
+            .map(|(i, tile)| (i % self.width, i / self.width, *tile))
+    }
+
+    /// Marks, in reading order, every tile a cell can reach from `(col, row)`
+    /// without crossing a wall or membrane.
+    pub fn flood_fill(&self, col: usize, row: usize) -> Vec<bool> {
+        self.flood_fill_by(col, row, Tile::is_wall)
+    }
+
+    /// Like `flood_fill`, stopping at the tiles for which `is_barrier` holds.
+    pub fn flood_fill_by(
+        &self,
+        col: usize,
+        row: usize,
+        is_barrier: impl Fn(Tile) -> bool,
+    ) -> Vec<bool> {
+        let mut reached = vec![false; self.tiles.len()];
+        let mut stack = vec![(col, row)];
+        while let Some((col, row)) = stack.pop() {
+            match self.get(col, row) {
+                Some(tile) if is_barrier(tile) => continue,
+                None => continue,
+                Some(_) if reached[row * self.width + col] => continue,
+                Some(_) => reached[row * self.width + col] = true,
+            }
+            stack.push((col + 1, row));
+            stack.push((col, row + 1));
+            if col > 0 {
+                stack.push((col - 1, row));
+            }
+            if row > 0 {
+                stack.push((col, row - 1));
+            }
+        }
+        reached
+    }
+}
+
+/// Writes the grid back out in the format `parse_level` reads.
+impl fmt::Display for LevelGrid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let header = &self.header;
+        if let Some(name) = &header.name {
+            writeln!(f, "name = {name}")?;
+        }
+        if let Some(glyphs) = &header.glyphs {
+            writeln!(f, "glyphs = {glyphs}")?;
+        }
+        if let Some(fire_rate) = header.fire_rate {
+            writeln!(f, "fire_rate = {fire_rate}")?;
+        }
+        if let Some(background) = header.background {
+            let [r, g, b, a] = background.as_rgba_f32().map(|c| (c * 255.0).round() as u8);
+            write!(f, "background = {r:02x}{g:02x}{b:02x}")?;
+            if a < u8::MAX {
+                write!(f, "{a:02x}")?;
+            }
+            writeln!(f)?;
+        }
+        if let Some(music) = &header.music {
+            writeln!(f, "music = {music}")?;
+        }
+        for ((glyph, index), properties) in &header.legend {
+            let mut values = Vec::new();
+            if let Some(health) = properties.health {
+                values.push(format!("health={health}"));
+            }
+            if let Some(fire_rate) = properties.fire_rate {
+                values.push(format!("fire_rate={fire_rate}"));
+            }
+            writeln!(f, "{glyph}{index}: {}", values.join(", "))?;
+        }
+        if !header.is_empty() {
+            writeln!(f, "---")?;
+        }
+        for row in self.tiles.chunks(self.width) {
+            let line: String = row.iter().map(|tile| tile.glyph()).collect();
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Tiles covered by the open interval `(min, max)` in tile units, clamped to the level.
+fn get_tile_span(min: f32, max: f32, len: usize) -> std::ops::Range<usize> {
+    let start = min.floor().clamp(0.0, len as f32) as usize;
+    let end = max.ceil().clamp(0.0, len as f32) as usize;
+    start..end.max(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_tiles(grid: &LevelGrid, translation: Vec3, size: Vec2) -> Vec<(usize, usize)> {
+        grid.overlapping_tiles(translation, size).map(|(col, row, _)| (col, row)).collect()
+    }
+
+    #[test]
+    fn tile_centers_round_trip_on_odd_and_even_sizes() {
+        for (width, height) in [(5, 5), (4, 4), (5, 4), (4, 7), (1, 1)] {
+            let grid = LevelGrid::filled(width, height, Tile::Floor);
+            for row in 0..height {
+                for col in 0..width {
+                    let center = grid.tile_to_world(col, row);
+                    assert_eq!(grid.world_to_tile(center), Some((col, row)), "{width}x{height}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn levels_are_centered_with_row_zero_at_the_top() {
+        let grid = LevelGrid::filled(5, 4, Tile::Floor);
+        assert_eq!(grid.tile_to_world(2, 0), Vec3::new(0.0, 1.5 * TILE_SIZE, 0.0));
+        assert_eq!(grid.tile_to_world(0, 3), Vec3::new(-2.0 * TILE_SIZE, -1.5 * TILE_SIZE, 0.0));
+        // On an even size the origin is the corner between four tiles.
+        assert_eq!(grid.world_to_tile(Vec3::ZERO), Some((2, 2)));
+    }
+
+    #[test]
+    fn positions_outside_the_level_have_no_tile() {
+        let grid = LevelGrid::filled(4, 3, Tile::Floor);
+        let half_width = 2.0 * TILE_SIZE;
+        let half_height = 1.5 * TILE_SIZE;
+        assert_eq!(grid.world_to_tile(Vec3::new(-half_width - 0.1, 0.0, 0.0)), None);
+        assert_eq!(grid.world_to_tile(Vec3::new(half_width, 0.0, 0.0)), None);
+        assert_eq!(grid.world_to_tile(Vec3::new(0.0, half_height + 0.1, 0.0)), None);
+        assert_eq!(grid.world_to_tile(Vec3::new(0.0, -half_height, 0.0)), None);
+        assert_eq!(grid.world_to_tile(Vec3::new(-half_width, half_height - 0.1, 0.0)), Some((0, 0)));
+        assert!(get_tiles(&grid, Vec3::new(10.0 * TILE_SIZE, 0.0, 0.0), Vec2::splat(TILE_SIZE)).is_empty());
+    }
+
+    #[test]
+    fn touching_edges_do_not_overlap() {
+        let grid = LevelGrid::filled(4, 4, Tile::Floor);
+        // Exactly covers tile (1, 0) and touches (0, 0), (2, 0) and (1, 1).
+        let center = grid.tile_to_world(1, 0);
+        assert_eq!(get_tiles(&grid, center, Vec2::splat(TILE_SIZE)), [(1, 0)]);
+        // A little wider reaches both neighbors in its row.
+        let wider = Vec2::new(TILE_SIZE + 0.1, TILE_SIZE);
+        assert_eq!(get_tiles(&grid, center, wider), [(0, 0), (1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn box_on_a_corner_overlaps_the_four_tiles_around_it() {
+        let grid = LevelGrid::filled(4, 4, Tile::Floor);
+        let tiles = get_tiles(&grid, Vec3::ZERO, Vec2::splat(2.0));
+        assert_eq!(tiles, [(1, 1), (2, 1), (1, 2), (2, 2)]);
+    }
+}
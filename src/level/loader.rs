@@ -0,0 +1,173 @@
+use bevy::{
+    asset::{AssetLoader, Error, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        render_resource::TextureFormat,
+        texture::{CompressedImageFormats, ImageType, TextureError}},
+    utils::BoxedFuture};
+use std::sync::{Arc, RwLock};
+use super::grid::{LevelGrid, MEMBRANE_COLOR, Tile, WEAK_WALL_COLOR};
+use super::parser::parse_level;
+use super::tiled::{parse_tiled_json, parse_tiled_xml};
+
+/// Tiles a level image can hold, with the prefab whose sprite color marks
+/// them and the color used when that prefab is not loaded.
+const IMAGE_TILES: [(Tile, &str, Color); 6] = [
+    (Tile::Wall, "wall", Color::DARK_GRAY),
+    (Tile::Membrane, "membrane", MEMBRANE_COLOR),
+    (Tile::WeakWall, "weak_wall", WEAK_WALL_COLOR),
+    (Tile::Player, "player", Color::ORANGE_RED),
+    (Tile::Enemy, "enemy", Color::FUCHSIA),
+    (Tile::Speed, "speed", Color::GREEN),
+];
+
+/// Pixel colors of a level image from the color of each tile's prefab,
+/// as given by `get_color`.
+pub fn get_image_palette(get_color: impl Fn(&str) -> Option<Color>) -> Vec<(Color, Tile)> {
+    IMAGE_TILES.iter()
+        .map(|(tile, prefab, default)| (get_color(prefab).unwrap_or(*default), *tile))
+        .collect()
+}
+
+/// The palette `ImageLevelLoader` reads images with, shared with the
+/// systems that keep it in step with the prefabs.
+#[derive(Resource, Clone)]
+pub struct ImagePalette(Arc<RwLock<Vec<(Color, Tile)>>>);
+
+impl Default for ImagePalette {
+    fn default() -> ImagePalette {
+        ImagePalette(Arc::new(RwLock::new(get_image_palette(|_| None))))
+    }
+}
+
+impl ImagePalette {
+    pub fn get(&self) -> Vec<(Color, Tile)> {
+        self.0.read().map_or_else(|error| error.into_inner().clone(), |palette| palette.clone())
+    }
+
+    pub fn set(&self, palette: Vec<(Color, Tile)>) {
+        match self.0.write() {
+            Ok(mut current) => *current = palette,
+            Err(error) => *error.into_inner() = palette,
+        }
+    }
+}
+
+#[derive(Debug, TypeUuid)]
+#[uuid = "6f46b179-5a4e-49b7-bbb2-ed3793af9161"]
+pub struct LevelAsset(pub LevelGrid);
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let grid = parse_level(std::str::from_utf8(bytes)?)?;
+            load_context.set_default_asset(LoadedAsset::new(LevelAsset(grid)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["lvl"]
+    }
+}
+
+/// Loads `.lvl.png` images drawn one pixel per tile.
+#[derive(Default)]
+pub struct ImageLevelLoader {
+    pub palette: ImagePalette,
+}
+
+impl AssetLoader for ImageLevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let grid = parse_level_image(bytes, &self.palette.get())?;
+            load_context.set_default_asset(LoadedAsset::new(LevelAsset(grid)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["lvl.png"]
+    }
+}
+
+/// Loads maps saved by Tiled, as XML `.tmx` or JSON `.tmj`.
+#[derive(Default)]
+pub struct TiledLevelLoader;
+
+impl AssetLoader for TiledLevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let source = std::str::from_utf8(bytes)?;
+            let grid = match load_context.path().extension() {
+                Some(extension) if extension == "tmj" => parse_tiled_json(source)?,
+                _ => parse_tiled_xml(source)?,
+            };
+            load_context.set_default_asset(LoadedAsset::new(LevelAsset(grid)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmx", "tmj"]
+    }
+}
+
+/// Translates each pixel to its glyph and parses the result, so an image
+/// is checked the same way as a text level. Alpha is ignored, so tiles with
+/// translucent prefabs are drawn opaque. Any pixel not in `palette` is floor.
+pub fn parse_level_image(bytes: &[u8], palette: &[(Color, Tile)]) -> Result<LevelGrid, Error> {
+    let image = Image::from_buffer(
+        bytes, ImageType::Extension("png"), CompressedImageFormats::NONE, true)?;
+    let format = image.texture_descriptor.format;
+    let image = image.convert(TextureFormat::Rgba8UnormSrgb)
+        .ok_or_else(|| TextureError::UnsupportedTextureFormat(format!("{format:?}")))?;
+    let width = image.texture_descriptor.size.width as usize;
+    let mut source = String::new();
+    for row in image.data.chunks(width * 4) {
+        for pixel in row.chunks(4) {
+            let tile = palette.iter()
+                .find(|(color, _)| color.as_rgba_u32().to_le_bytes()[..3] == pixel[..3])
+                .map_or(Tile::Floor, |(_, tile)| *tile);
+            source.push(tile.glyph());
+        }
+        source.push('\n');
+    }
+    Ok(parse_level(&source)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_image_matches_text_level() {
+        let image = include_bytes!("../../tests/fixtures/sample.lvl.png");
+        let grid = parse_level_image(image, &get_image_palette(|_| None)).unwrap();
+        let text = parse_level(include_str!("../../tests/fixtures/sample.lvl")).unwrap();
+        assert_eq!(grid, text);
+    }
+
+    #[test]
+    fn palette_follows_prefab_colors() {
+        let palette = get_image_palette(|prefab| (prefab == "wall").then_some(Color::BLUE));
+        assert!(palette.contains(&(Color::BLUE, Tile::Wall)));
+        assert!(palette.contains(&(Color::FUCHSIA, Tile::Enemy)));
+    }
+}
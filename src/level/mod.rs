@@ -0,0 +1,400 @@
+use bevy::{audio::AudioSink, prelude::*, utils::HashMap};
+use crate::AppState;
+use crate::components::*;
+use crate::loading::RequiredAssets;
+use crate::scene::{Prefabs, get_prefab_color, spawn_prefab};
+
+mod campaign;
+mod collision;
+mod daily;
+mod difficulty;
+mod generator;
+mod grid;
+mod loader;
+mod parser;
+mod registry;
+mod tiled;
+mod tilemap;
+
+use campaign::CampaignLoader;
+use daily::get_daily_seed;
+use difficulty::{DIFFICULTY_PATH, DifficultyCurve, DifficultyCurveLoader};
+use generator::generate;
+use grid::LevelHeader;
+
+pub use campaign::{CAMPAIGN_PATH, Campaign, CampaignManifest};
+pub use collision::{move_bouncing, sweep_boundary};
+pub use daily::{format_day, get_local_day};
+pub use loader::{
+    ImageLevelLoader, ImagePalette, LevelAsset, LevelLoader, TiledLevelLoader,
+    get_image_palette, parse_level_image};
+pub use grid::{LevelGrid, MEMBRANE_COLOR, TILE_SIZE, Tile, TileProperties, WEAK_WALL_COLOR};
+pub use parser::{get_first_row_line, parse_level};
+pub use registry::{DEFAULT_GLYPHS, RegisterTile, TileRegistry};
+pub use tiled::{parse_tiled_json, parse_tiled_xml};
+pub use tilemap::{build_tilemap, get_chunks};
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        let palette = ImagePalette::default();
+        app
+        .add_asset::<LevelAsset>()
+        .add_asset::<CampaignManifest>()
+        .add_asset::<DifficultyCurve>()
+        .init_asset_loader::<CampaignLoader>()
+        .init_asset_loader::<DifficultyCurveLoader>()
+        .init_asset_loader::<LevelLoader>()
+        .add_asset_loader(ImageLevelLoader { palette: palette.clone() })
+        .insert_resource(palette)
+        .init_asset_loader::<TiledLevelLoader>()
+        .init_resource::<CurrentLevel>()
+        .init_resource::<EnemiesRemaining>()
+        .init_resource::<LevelSource>()
+        .init_resource::<LevelMusic>()
+        .register_tile(DEFAULT_GLYPHS, '*', spawn_speed)
+        .register_tile(DEFAULT_GLYPHS, 'P', spawn_player)
+        .register_tile(DEFAULT_GLYPHS, 'E', spawn_enemy)
+        .add_startup_system(load_campaign)
+        .add_startup_system(load_difficulty_curve)
+        .add_system(update_image_palette)
+        .add_system(update_difficulty_curve)
+        .add_system_set(SystemSet::on_exit(AppState::Loading)
+            .with_system(create_campaign))
+        .add_system_set(SystemSet::on_update(AppState::Game)
+            .with_system(reload_level)
+            .with_system(reload_prefabs)
+            .with_system(frame_camera)
+            .with_system(update_tile_position)
+            .with_system(build_tilemap)
+            .with_system(apply_level_header))
+        .add_system_set(SystemSet::on_exit(AppState::Game)
+            .with_system(remove_level_grid)
+            .with_system(reset_level_header));
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct CurrentLevel(pub Handle<LevelAsset>);
+
+/// Kept in sync by the game with the live `Enemy` entities.
+#[derive(Resource, Default)]
+pub struct EnemiesRemaining(pub usize);
+
+/// Health left in each weak wall of the current level, by `(col, row)`.
+#[derive(Resource, Default)]
+pub struct WallHealth(pub HashMap<(usize, usize), i32>);
+
+/// Weak walls without a legend entry break on the first explosion.
+const WEAK_WALL_HEALTH: i32 = 1;
+
+/// Where `generate_level` takes its levels from.
+#[derive(Resource, Default)]
+pub enum LevelSource {
+    #[default]
+    Assets,
+    /// A generated run, which goes on for as long as the player lasts.
+    /// `level` counts the levels cleared and sets the difficulty.
+    Generated { seed: u64, level: usize },
+    /// A generated run seeded by the local date, in days since 1970-01-01.
+    Daily { day: i64, level: usize },
+    /// A grid handed over by the editor, played without saving it first.
+    Playtest(LevelGrid),
+}
+
+impl LevelSource {
+    /// Moves a generated run on to its next level.
+    pub fn advance(&mut self) {
+        if let LevelSource::Generated { level, .. } | LevelSource::Daily { level, .. } = self {
+            *level += 1;
+        }
+    }
+
+    /// Takes a generated run back to its first level, as when it is played again.
+    pub fn restart(&mut self) {
+        if let LevelSource::Generated { level, .. } | LevelSource::Daily { level, .. } = self {
+            *level = 0;
+        }
+    }
+}
+
+/// The looping music of the level being played, if it has any.
+#[derive(Resource, Default)]
+struct LevelMusic(Option<Handle<AudioSink>>);
+
+/// Everything a level spawns, which is despawned when it is replaced.
+pub type LevelEntity = Or<(
+    With<Wall>, With<Cell>, With<StatusEffect>,
+    With<Particle>, With<Virus>, With<Explosion>)>;
+
+/// The campaign manifest, kept loaded so the `Campaign` can be built from it.
+#[derive(Resource)]
+struct CampaignHandle(Handle<CampaignManifest>);
+
+fn load_campaign(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut required: ResMut<RequiredAssets>,
+) {
+    let handle = asset_server.load(CAMPAIGN_PATH);
+    required.0.push(handle.clone_untyped());
+    commands.insert_resource(CampaignHandle(handle));
+}
+
+fn create_campaign(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    handle: Res<CampaignHandle>,
+    manifests: Res<Assets<CampaignManifest>>,
+) {
+    if let Some(manifest) = manifests.get(&handle.0) {
+        commands.insert_resource(Campaign::new(manifest, &asset_server));
+    }
+}
+
+/// The difficulty curve, kept loaded so that edits to it are picked up.
+#[derive(Resource)]
+struct DifficultyCurveHandle(Handle<DifficultyCurve>);
+
+fn load_difficulty_curve(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut required: ResMut<RequiredAssets>,
+) {
+    let handle = asset_server.load(DIFFICULTY_PATH);
+    required.0.push(handle.clone_untyped());
+    commands.insert_resource(DifficultyCurveHandle(handle));
+}
+
+/// Copies the curve into a resource once loaded and again on every edit.
+/// An edit the loader rejects keeps the last good curve.
+fn update_difficulty_curve(
+    mut commands: Commands,
+    mut reader: EventReader<AssetEvent<DifficultyCurve>>,
+    handle: Res<DifficultyCurveHandle>,
+    curves: Res<Assets<DifficultyCurve>>,
+) {
+    if reader.iter().count() > 0 {
+        if let Some(curve) = curves.get(&handle.0) {
+            commands.insert_resource(curve.clone());
+        }
+    }
+}
+
+/// Level images are read in the colors their tiles' prefabs are drawn with.
+fn update_image_palette(
+    mut reader: EventReader<AssetEvent<DynamicScene>>,
+    scenes: Res<Assets<DynamicScene>>,
+    prefabs: Res<Prefabs>,
+    palette: Res<ImagePalette>,
+) {
+    if reader.iter().count() > 0 {
+        palette.set(get_image_palette(|name| get_prefab_color(&scenes, &prefabs, name)));
+    }
+}
+
+pub fn generate_level(
+    mut commands: Commands,
+    mut campaign: ResMut<Campaign>,
+    mut current: ResMut<CurrentLevel>,
+    mut levels: ResMut<Assets<LevelAsset>>,
+    source: Res<LevelSource>,
+    registry: Res<TileRegistry>,
+    curve: Res<DifficultyCurve>,
+) {
+    campaign.time = 0.0;
+    let generated = |seed: u64, level: usize| {
+        generate(seed.wrapping_add(level as u64), &curve.get(level)).map(LevelAsset)
+    };
+    let asset = match &*source {
+        LevelSource::Assets => Ok(None),
+        LevelSource::Generated { seed, level } => generated(*seed, *level).map(Some),
+        LevelSource::Daily { day, level } => generated(get_daily_seed(*day), *level).map(Some),
+        LevelSource::Playtest(grid) => Ok(Some(LevelAsset(grid.clone()))),
+    };
+    current.0 = match asset {
+        Ok(Some(asset)) => levels.add(asset),
+        Ok(None) => campaign.handles[campaign.index].clone(),
+        Err(error) => {
+            error!("could not generate a level: {error}");
+            commands.add(|world: &mut World| {
+                world.resource_mut::<State<AppState>>().set(AppState::Splash).ok();
+            });
+            return;
+        }
+    };
+    // Not loaded yet: reload_level spawns it once the asset is created.
+    if let Some(asset) = levels.get(&current.0) {
+        spawn_level(&mut commands, &registry, &asset.0);
+    }
+}
+
+fn reload_level(
+    mut commands: Commands,
+    mut reader: EventReader<AssetEvent<LevelAsset>>,
+    current: Res<CurrentLevel>,
+    levels: Res<Assets<LevelAsset>>,
+    registry: Res<TileRegistry>,
+    query: Query<Entity, LevelEntity>,
+) {
+    let has_changed = reader.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => *handle == current.0,
+        AssetEvent::Removed { .. } => false,
+    });
+    if let Some(asset) = levels.get(&current.0).filter(|_| has_changed) {
+        for entity in &query {
+            commands.entity(entity).despawn_recursive();
+        }
+        spawn_level(&mut commands, &registry, &asset.0);
+    }
+}
+
+/// Tuning a prefab respawns the level with the new values, by marking the
+/// level modified for reload_level.
+fn reload_prefabs(
+    mut reader: EventReader<AssetEvent<DynamicScene>>,
+    prefabs: Res<Prefabs>,
+    current: Res<CurrentLevel>,
+    mut levels: ResMut<Assets<LevelAsset>>,
+) {
+    let has_changed = reader.iter().any(|event| match event {
+        AssetEvent::Modified { handle } => prefabs.0.values().any(|prefab| prefab == handle),
+        _ => false,
+    });
+    if has_changed {
+        levels.get_mut(&current.0);
+    }
+}
+
+fn spawn_level(commands: &mut Commands, registry: &TileRegistry, grid: &LevelGrid) {
+    commands.insert_resource(grid.clone());
+    commands.insert_resource(get_wall_health(grid));
+    let set = grid.header.glyphs.as_deref();
+    // Walls and membranes are drawn by build_tilemap once the new grid is in place.
+    let tiles = grid.iter().filter(|(_, _, tile)| *tile != Tile::Floor && !tile.is_wall());
+    let mut counts = HashMap::new();
+    for (col, row, tile) in tiles {
+        let count = counts.entry(tile.glyph()).or_insert(0);
+        *count += 1;
+        let mut properties = grid.header.legend.get(&(tile.glyph(), *count))
+            .copied()
+            .unwrap_or_default();
+        properties.fire_rate = properties.fire_rate.or(grid.header.fire_rate);
+        match registry.get(set, tile.glyph()) {
+            Some(spawner) => {
+                let entity = spawner(commands, grid.tile_to_world(col, row), &properties);
+                commands.entity(entity).insert(TilePosition(col, row));
+            }
+            None => error!("no spawner for glyph '{}' in glyph set {:?}", tile.glyph(), set),
+        }
+    }
+}
+
+fn get_wall_health(grid: &LevelGrid) -> WallHealth {
+    let walls = grid.iter().filter(|(_, _, tile)| *tile == Tile::WeakWall);
+    let health = walls.enumerate()
+        .map(|(i, (col, row, tile))| {
+            let health = grid.header.legend.get(&(tile.glyph(), i + 1))
+                .and_then(|properties| properties.health)
+                .unwrap_or(WEAK_WALL_HEALTH);
+            ((col, row), health)
+        })
+        .collect();
+    WallHealth(health)
+}
+
+fn spawn_speed(commands: &mut Commands, translation: Vec3, _: &TileProperties) -> Entity {
+    spawn_prefab(commands, "speed", translation)
+}
+
+fn spawn_player(commands: &mut Commands, translation: Vec3, properties: &TileProperties) -> Entity {
+    let entity = spawn_prefab(commands, "player", translation);
+    if let Some(health) = properties.health {
+        commands.entity(entity).insert(Lifespan(health));
+    }
+    entity
+}
+
+fn spawn_enemy(commands: &mut Commands, translation: Vec3, properties: &TileProperties) -> Entity {
+    let entity = spawn_prefab(commands, "enemy", translation);
+    if let Some(health) = properties.health {
+        commands.entity(entity).insert(Lifespan(health));
+    }
+    if let Some(fire_rate) = properties.fire_rate {
+        commands.entity(entity).insert(Enemy(Timer::from_seconds(1.0 / fire_rate, TimerMode::Repeating)));
+    }
+    entity
+}
+
+fn apply_level_header(
+    grid: Option<Res<LevelGrid>>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    sinks: Res<Assets<AudioSink>>,
+    mut music: ResMut<LevelMusic>,
+    mut clear_color: ResMut<ClearColor>,
+    mut applied: Local<LevelHeader>,
+) {
+    // Destroyed walls change the grid but not its header.
+    let Some(grid) = grid.filter(|grid| grid.is_added() || grid.header != *applied) else { return };
+    *applied = grid.header.clone();
+    clear_color.0 = grid.header.background.unwrap_or(Color::BLACK);
+    stop_music(&sinks, &mut music);
+    if let Some(path) = &grid.header.music {
+        let sink = audio.play_with_settings(asset_server.load(path.as_str()), PlaybackSettings::LOOP);
+        music.0 = Some(sinks.get_handle(sink));
+    }
+}
+
+fn reset_level_header(
+    sinks: Res<Assets<AudioSink>>,
+    mut music: ResMut<LevelMusic>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    clear_color.0 = Color::BLACK;
+    stop_music(&sinks, &mut music);
+}
+
+fn stop_music(sinks: &Assets<AudioSink>, music: &mut LevelMusic) {
+    if let Some(sink) = music.0.take().and_then(|handle| sinks.get(&handle)) {
+        sink.stop();
+    }
+}
+
+fn update_tile_position(
+    grid: Option<Res<LevelGrid>>,
+    mut query: Query<(&Transform, &mut TilePosition), Changed<Transform>>,
+) {
+    if let Some(grid) = grid {
+        for (transform, mut position) in query.iter_mut() {
+            if let Some((col, row)) = grid.world_to_tile(transform.translation) {
+                if (position.0, position.1) != (col, row) {
+                    *position = TilePosition(col, row);
+                }
+            }
+        }
+    }
+}
+
+pub fn remove_level_grid(mut commands: Commands) {
+    commands.remove_resource::<LevelGrid>();
+    commands.remove_resource::<WallHealth>();
+}
+
+/// Scales the camera so the whole level fits in the window.
+pub fn frame_camera(
+    grid: Option<Res<LevelGrid>>,
+    windows: Res<Windows>,
+    mut camera_query: Query<&mut OrthographicProjection, With<MainCamera>>,
+) {
+    if let Some(grid) = grid {
+        let window = windows.get_primary().unwrap();
+        let width = grid.width as f32 * TILE_SIZE / window.width();
+        let height = grid.height as f32 * TILE_SIZE / window.height();
+        let mut projection = camera_query.single_mut();
+        if projection.scale != width.max(height) {
+            projection.scale = width.max(height);
+        }
+    }
+}
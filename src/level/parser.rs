@@ -0,0 +1,261 @@
+use bevy::prelude::Color;
+use std::{error::Error, fmt};
+use super::grid::{LevelGrid, LevelHeader, Tile, TileProperties};
+
+/// Rows are lines of the file, counting the header, and columns are
+/// 1-based, so they match what an editor shows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LevelError {
+    MalformedHeader { line: usize },
+    UnknownKey { line: usize, key: String },
+    InvalidValue { line: usize, key: String },
+    MissingLegendTile { glyph: char, index: usize },
+    Empty,
+    RowWidth { row: usize, expected: usize, found: usize },
+    UnknownGlyph { row: usize, col: usize, glyph: char },
+    MissingPlayer,
+    MultiplePlayers { row: usize, col: usize },
+    MissingEnemy,
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::MalformedHeader { line } => write!(f,
+                "header line {line} is not a `key = value` pair"),
+            LevelError::UnknownKey { line, key } => write!(f,
+                "unknown header key '{key}' on line {line}"),
+            LevelError::InvalidValue { line, key } => write!(f,
+                "invalid value for header key '{key}' on line {line}"),
+            LevelError::MissingLegendTile { glyph, index } => write!(f,
+                "legend entry {glyph}{index} has no matching '{glyph}' in the level"),
+            LevelError::Empty => write!(f, "level is empty"),
+            LevelError::RowWidth { row, expected, found } => write!(f,
+                "row {row} is {found} tiles wide, expected {expected}"),
+            LevelError::UnknownGlyph { row, col, glyph } => write!(f,
+                "unknown glyph '{glyph}' at row {row}, column {col}"),
+            LevelError::MissingPlayer => write!(f, "level has no player spawn 'P'"),
+            LevelError::MultiplePlayers { row, col } => write!(f,
+                "second player spawn 'P' at row {row}, column {col}"),
+            LevelError::MissingEnemy => write!(f, "level has no enemy 'E'"),
+        }
+    }
+}
+
+impl Error for LevelError {}
+
+pub fn parse_level(source: &str) -> Result<LevelGrid, LevelError> {
+    let lines: Vec<&str> = source.lines().map(str::trim_end).collect();
+    let separator = lines.iter().position(|line| *line == "---");
+    let header = match separator {
+        Some(separator) => parse_header(&lines[..separator])?,
+        None => LevelHeader::default(),
+    };
+    let first = get_first_row(&lines).ok_or(LevelError::Empty)?;
+    let last = lines.iter().rposition(|line| !line.is_empty()).ok_or(LevelError::Empty)?;
+    let rows = &lines[first..=last];
+    let width = rows[0].chars().count();
+    let mut tiles = Vec::with_capacity(width * rows.len());
+    let mut has_player = false;
+    let mut has_enemy = false;
+    for (i, line) in rows.iter().enumerate() {
+        let row = first + i + 1;
+        let found = line.chars().count();
+        if found != width {
+            return Err(LevelError::RowWidth { row, expected: width, found });
+        }
+        for (j, glyph) in line.chars().enumerate() {
+            let col = j + 1;
+            let tile = match Tile::from_glyph(glyph) {
+                Some(tile) => tile,
+                // Custom glyphs are checked against the registry at spawn time.
+                None if header.glyphs.is_some() && !glyph.is_whitespace() => Tile::Custom(glyph),
+                None => return Err(LevelError::UnknownGlyph { row, col, glyph }),
+            };
+            match tile {
+                Tile::Player if has_player => {
+                    return Err(LevelError::MultiplePlayers { row, col });
+                }
+                Tile::Player => has_player = true,
+                Tile::Enemy => has_enemy = true,
+                _ => {}
+            }
+            tiles.push(tile);
+        }
+    }
+    if !has_player {
+        return Err(LevelError::MissingPlayer);
+    }
+    if !has_enemy {
+        return Err(LevelError::MissingEnemy);
+    }
+    for &(glyph, index) in header.legend.keys() {
+        if tiles.iter().filter(|tile| tile.glyph() == glyph).count() < index {
+            return Err(LevelError::MissingLegendTile { glyph, index });
+        }
+    }
+    Ok(LevelGrid { header, width, height: rows.len(), tiles })
+}
+
+/// The 1-based line of the file on which the grid's top row is, past the
+/// header and any blank lines. Rows of a parsed grid are reported from here.
+pub fn get_first_row_line(source: &str) -> usize {
+    let lines: Vec<&str> = source.lines().map(str::trim_end).collect();
+    get_first_row(&lines).map_or(1, |first| first + 1)
+}
+
+/// Index of the first non-blank line after the header, if there is one.
+fn get_first_row(lines: &[&str]) -> Option<usize> {
+    let body = lines.iter().position(|line| *line == "---").map_or(0, |separator| separator + 1);
+    lines[body..].iter().position(|line| !line.is_empty()).map(|first| body + first)
+}
+
+fn parse_header(lines: &[&str]) -> Result<LevelHeader, LevelError> {
+    let mut header = LevelHeader::default();
+    for (i, line) in lines.iter().enumerate() {
+        let line_number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once(':').filter(|(key, _)| !key.contains('=')) {
+            let key = parse_legend_key(key.trim())
+                .ok_or(LevelError::MalformedHeader { line: line_number })?;
+            header.legend.insert(key, parse_properties(value, line_number)?);
+            continue;
+        }
+        let (key, value) = line.split_once('=')
+            .ok_or(LevelError::MalformedHeader { line: line_number })?;
+        let key = key.trim();
+        let value = value.trim().to_string();
+        let invalid = || LevelError::InvalidValue { line: line_number, key: key.to_string() };
+        match key {
+            "name" => header.name = Some(value),
+            "glyphs" => header.glyphs = Some(value),
+            "fire_rate" => {
+                let fire_rate = value.parse().ok().filter(|rate: &f32| *rate > 0.0);
+                header.fire_rate = Some(fire_rate.ok_or_else(invalid)?);
+            }
+            "background" => {
+                let hex = value.trim_start_matches('#');
+                header.background = Some(Color::hex(hex).map_err(|_| invalid())?);
+            }
+            "music" => header.music = Some(value),
+            _ => return Err(LevelError::UnknownKey { line: line_number, key: key.to_string() }),
+        }
+    }
+    Ok(header)
+}
+
+/// `E1` is the first `E` of the level in reading order.
+fn parse_legend_key(key: &str) -> Option<(char, usize)> {
+    let mut chars = key.chars();
+    let glyph = chars.next()?;
+    let index = chars.as_str().parse().ok().filter(|index| *index > 0)?;
+    Some((glyph, index))
+}
+
+fn parse_properties(source: &str, line: usize) -> Result<TileProperties, LevelError> {
+    let mut properties = TileProperties::default();
+    for property in source.split(',').filter(|property| !property.trim().is_empty()) {
+        let (key, value) = property.split_once('=')
+            .ok_or(LevelError::MalformedHeader { line })?;
+        let key = key.trim();
+        let value = value.trim();
+        let invalid = || LevelError::InvalidValue { line, key: key.to_string() };
+        match key {
+            "health" => {
+                let health = value.parse().ok().filter(|health: &i32| *health > 0);
+                properties.health = Some(health.ok_or_else(invalid)?);
+            }
+            "fire_rate" => {
+                let fire_rate = value.parse().ok().filter(|rate: &f32| *rate > 0.0);
+                properties.fire_rate = Some(fire_rate.ok_or_else(invalid)?);
+            }
+            _ => return Err(LevelError::UnknownKey { line, key: key.to_string() }),
+        }
+    }
+    Ok(properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_level() {
+        let grid = parse_level("|||||\n|P.E|\n|.*.|\n|||||\n").unwrap();
+        assert_eq!((grid.width, grid.height), (5, 4));
+        assert_eq!(grid.get(1, 1), Some(Tile::Player));
+        assert_eq!(grid.get(3, 1), Some(Tile::Enemy));
+        assert_eq!(grid.get(2, 2), Some(Tile::Speed));
+        assert_eq!(grid.get(0, 3), Some(Tile::Wall));
+        assert!(grid.header.is_empty());
+    }
+
+    #[test]
+    fn valid_level_with_header() {
+        let source = "name = Test\nfire_rate = 0.5\nE1: health=3\n---\n|||||\n|P.E|\n|||||\n";
+        let grid = parse_level(source).unwrap();
+        assert_eq!(grid.header.name.as_deref(), Some("Test"));
+        assert_eq!(grid.header.fire_rate, Some(0.5));
+        let properties = TileProperties { health: Some(3), fire_rate: None };
+        assert_eq!(grid.header.legend.get(&('E', 1)), Some(&properties));
+        assert_eq!(parse_level(&grid.to_string()), Ok(grid));
+    }
+
+    #[test]
+    fn empty_level() {
+        assert_eq!(parse_level("\n\n"), Err(LevelError::Empty));
+    }
+
+    #[test]
+    fn row_width() {
+        let error = parse_level("|||||\n|P.E|\n|..|\n|||||\n");
+        assert_eq!(error, Err(LevelError::RowWidth { row: 3, expected: 5, found: 4 }));
+    }
+
+    #[test]
+    fn rows_count_the_header_and_blank_lines() {
+        let source = "name = Test\nE1: health=3\n---\n\n|||||\n|P.E|\n|.x.|\n|||||\n";
+        assert_eq!(get_first_row_line(source), 5);
+        assert_eq!(parse_level(source), Err(LevelError::UnknownGlyph { row: 7, col: 3, glyph: 'x' }));
+        let error = parse_level("\n\n|||||\n|P.E|\n|..|\n|||||\n");
+        assert_eq!(error, Err(LevelError::RowWidth { row: 5, expected: 5, found: 4 }));
+        assert_eq!(get_first_row_line("|||||\n"), 1);
+    }
+
+    #[test]
+    fn unknown_glyph() {
+        let error = parse_level("|||||\n|P.E|\n|.x.|\n|||||\n");
+        assert_eq!(error, Err(LevelError::UnknownGlyph { row: 3, col: 3, glyph: 'x' }));
+    }
+
+    #[test]
+    fn missing_player() {
+        assert_eq!(parse_level("|||||\n|..E|\n|||||\n"), Err(LevelError::MissingPlayer));
+    }
+
+    #[test]
+    fn multiple_players() {
+        let error = parse_level("|||||\n|P.E|\n|..P|\n|||||\n");
+        assert_eq!(error, Err(LevelError::MultiplePlayers { row: 3, col: 4 }));
+    }
+
+    #[test]
+    fn missing_enemy() {
+        assert_eq!(parse_level("|||||\n|P..|\n|||||\n"), Err(LevelError::MissingEnemy));
+    }
+
+    #[test]
+    fn header_errors() {
+        let level = "---\n|||||\n|P.E|\n|||||\n";
+        assert_eq!(parse_level(&format!("name\n{level}")),
+            Err(LevelError::MalformedHeader { line: 1 }));
+        assert_eq!(parse_level(&format!("name = a\nspeed = 2\n{level}")),
+            Err(LevelError::UnknownKey { line: 2, key: "speed".to_string() }));
+        assert_eq!(parse_level(&format!("fire_rate = -1\n{level}")),
+            Err(LevelError::InvalidValue { line: 1, key: "fire_rate".to_string() }));
+        assert_eq!(parse_level(&format!("E2: health=1\n{level}")),
+            Err(LevelError::MissingLegendTile { glyph: 'E', index: 2 }));
+    }
+}
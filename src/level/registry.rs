@@ -0,0 +1,56 @@
+use bevy::{prelude::*, utils::HashMap};
+use super::grid::TileProperties;
+
+pub const DEFAULT_GLYPHS: &str = "default";
+
+pub type TileSpawner = Box<dyn Fn(&mut Commands, Vec3, &TileProperties) -> Entity + Send + Sync>;
+
+/// Maps level glyphs to the entities they spawn, grouped into named glyph
+/// sets. A level that declares `glyphs = <set>` looks up its glyphs in that
+/// set first and falls back to the default set.
+#[derive(Resource, Default)]
+pub struct TileRegistry {
+    sets: HashMap<String, HashMap<char, TileSpawner>>,
+}
+
+impl TileRegistry {
+    pub fn register(
+        &mut self,
+        set: &str,
+        glyph: char,
+        spawner: impl Fn(&mut Commands, Vec3, &TileProperties) -> Entity + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.sets.entry(set.to_string())
+            .or_default()
+            .insert(glyph, Box::new(spawner));
+        self
+    }
+
+    pub fn get(&self, set: Option<&str>, glyph: char) -> Option<&TileSpawner> {
+        set.and_then(|set| self.sets.get(set))
+            .and_then(|spawners| spawners.get(&glyph))
+            .or_else(|| self.sets.get(DEFAULT_GLYPHS)?.get(&glyph))
+    }
+}
+
+pub trait RegisterTile {
+    fn register_tile(
+        &mut self,
+        set: &str,
+        glyph: char,
+        spawner: impl Fn(&mut Commands, Vec3, &TileProperties) -> Entity + Send + Sync + 'static,
+    ) -> &mut Self;
+}
+
+impl RegisterTile for App {
+    fn register_tile(
+        &mut self,
+        set: &str,
+        glyph: char,
+        spawner: impl Fn(&mut Commands, Vec3, &TileProperties) -> Entity + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.init_resource::<TileRegistry>();
+        self.world.resource_mut::<TileRegistry>().register(set, glyph, spawner);
+        self
+    }
+}
@@ -0,0 +1,440 @@
+use roxmltree::{Document, Node};
+use serde::Deserialize;
+use std::{collections::HashMap, error::Error, fmt};
+use super::grid::{LevelGrid, Tile};
+use super::parser::{LevelError, parse_level};
+
+/// Tiled keeps flip and rotation flags in the high bits of a tile id.
+const GID_MASK: u32 = 0x0fff_ffff;
+
+#[derive(Debug)]
+pub enum TiledError {
+    Json(serde_json::Error),
+    Xml(roxmltree::Error),
+    MissingElement { element: String },
+    Attribute { element: String, attribute: String },
+    ExternalTileset { source: String },
+    LayerData { name: String },
+    LayerSize { name: String, expected: usize, found: usize },
+    ObjectOutside { name: String },
+    Level(LevelError),
+}
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TiledError::Json(error) => write!(f, "{error}"),
+            TiledError::Xml(error) => write!(f, "{error}"),
+            TiledError::MissingElement { element } => write!(f,
+                "missing <{element}> element"),
+            TiledError::Attribute { element, attribute } => write!(f,
+                "<{element}> has a missing or invalid '{attribute}'"),
+            TiledError::ExternalTileset { source } => write!(f,
+                "tileset '{source}' must be embedded in the map"),
+            TiledError::LayerData { name } => write!(f,
+                "layer '{name}' must be finite and CSV encoded"),
+            TiledError::LayerSize { name, expected, found } => write!(f,
+                "layer '{name}' has {found} tiles, expected {expected}"),
+            TiledError::ObjectOutside { name } => write!(f,
+                "object '{name}' is outside the map"),
+            TiledError::Level(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for TiledError {}
+
+impl From<serde_json::Error> for TiledError {
+    fn from(error: serde_json::Error) -> TiledError {
+        TiledError::Json(error)
+    }
+}
+
+impl From<roxmltree::Error> for TiledError {
+    fn from(error: roxmltree::Error) -> TiledError {
+        TiledError::Xml(error)
+    }
+}
+
+impl From<LevelError> for TiledError {
+    fn from(error: LevelError) -> TiledError {
+        TiledError::Level(error)
+    }
+}
+
+/// What a level needs from a Tiled map, whichever format it was saved in.
+#[derive(Default)]
+struct TiledMap {
+    width: usize,
+    height: usize,
+    tile_width: f32,
+    tile_height: f32,
+    /// Tiles by global tile id, for the tileset tiles that map to one.
+    tiles: HashMap<u32, Tile>,
+    layers: Vec<TiledLayer>,
+    objects: Vec<TiledObject>,
+}
+
+struct TiledLayer {
+    name: String,
+    data: Vec<u32>,
+}
+
+#[derive(Default)]
+struct TiledObject {
+    name: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    gid: Option<u32>,
+    tile: Option<Tile>,
+}
+
+type Properties = Vec<(String, String)>;
+
+/// A tileset tile or object becomes a level tile through its class, or
+/// through a string property named `tile`, set to one of these names.
+fn get_tile(class: &str, properties: &[(String, String)]) -> Option<Tile> {
+    let tile = properties.iter()
+        .find(|(name, _)| name == "tile")
+        .map_or(class, |(_, value)| value.as_str());
+    match tile {
+        "Floor" => Some(Tile::Floor),
+        "Wall" => Some(Tile::Wall),
+        "Membrane" => Some(Tile::Membrane),
+        "WeakWall" => Some(Tile::WeakWall),
+        "Player" => Some(Tile::Player),
+        "Enemy" => Some(Tile::Enemy),
+        "StatusEffect" => Some(Tile::Speed),
+        _ => None,
+    }
+}
+
+/// Stacks the tile layers in order, places the objects on top and checks
+/// the result the same way as a text level.
+fn build_grid(map: &TiledMap) -> Result<LevelGrid, TiledError> {
+    let mut tiles = vec![Tile::Floor; map.width * map.height];
+    for layer in &map.layers {
+        if layer.data.len() != tiles.len() {
+            let (expected, found) = (tiles.len(), layer.data.len());
+            return Err(TiledError::LayerSize { name: layer.name.clone(), expected, found });
+        }
+        for (tile, gid) in tiles.iter_mut().zip(&layer.data) {
+            if let Some(kind) = map.tiles.get(&(gid & GID_MASK)) {
+                *tile = *kind;
+            }
+        }
+    }
+    for object in &map.objects {
+        let kind = object.tile
+            .or_else(|| map.tiles.get(&(object.gid? & GID_MASK)).copied());
+        let Some(kind) = kind else { continue };
+        // Tile objects hang from their bottom-left corner, others from the top-left.
+        let top = if object.gid.is_some() { object.y - object.height } else { object.y };
+        let col = ((object.x + object.width / 2.0) / map.tile_width).floor();
+        let row = ((top + object.height / 2.0) / map.tile_height).floor();
+        if col < 0.0 || row < 0.0 || col as usize >= map.width || row as usize >= map.height {
+            return Err(TiledError::ObjectOutside { name: object.name.clone() });
+        }
+        tiles[row as usize * map.width + col as usize] = kind;
+    }
+    let mut source = String::with_capacity(tiles.len() + map.height);
+    for row in tiles.chunks(map.width.max(1)) {
+        source.extend(row.iter().map(|tile| tile.glyph()));
+        source.push('\n');
+    }
+    Ok(parse_level(&source)?)
+}
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: usize,
+    height: usize,
+    tilewidth: f32,
+    tileheight: f32,
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonObject {
+    #[serde(default)]
+    name: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    gid: Option<u32>,
+    #[serde(default, rename = "type", alias = "class")]
+    class: String,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+}
+
+#[derive(Deserialize)]
+struct JsonTile {
+    id: u32,
+    #[serde(default, rename = "type", alias = "class")]
+    class: String,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+fn get_json_properties(properties: Vec<JsonProperty>) -> Properties {
+    properties.into_iter()
+        .filter_map(|property| Some((property.name, property.value.as_str()?.to_string())))
+        .collect()
+}
+
+/// Reads a map saved by Tiled as JSON (`.tmj`).
+pub fn parse_tiled_json(source: &str) -> Result<LevelGrid, TiledError> {
+    let json: JsonMap = serde_json::from_str(source)?;
+    let mut map = TiledMap {
+        width: json.width,
+        height: json.height,
+        tile_width: json.tilewidth,
+        tile_height: json.tileheight,
+        ..Default::default()
+    };
+    for tileset in json.tilesets {
+        if let Some(source) = tileset.source {
+            return Err(TiledError::ExternalTileset { source });
+        }
+        for tile in tileset.tiles {
+            if let Some(kind) = get_tile(&tile.class, &get_json_properties(tile.properties)) {
+                map.tiles.insert(tileset.firstgid + tile.id, kind);
+            }
+        }
+    }
+    add_json_layers(&mut map, json.layers)?;
+    build_grid(&map)
+}
+
+fn add_json_layers(map: &mut TiledMap, layers: Vec<JsonLayer>) -> Result<(), TiledError> {
+    for layer in layers {
+        // Encoded layers hold a string and infinite ones no data at all.
+        let data = layer.data.and_then(|data| serde_json::from_value(data).ok());
+        match (layer.kind.as_str(), data) {
+            ("tilelayer", Some(data)) => {
+                map.layers.push(TiledLayer { name: layer.name, data });
+            }
+            ("tilelayer", _) => return Err(TiledError::LayerData { name: layer.name }),
+            ("objectgroup", _) => {
+                for object in layer.objects {
+                    let properties = get_json_properties(object.properties);
+                    map.objects.push(TiledObject {
+                        tile: get_tile(&object.class, &properties),
+                        name: object.name,
+                        x: object.x,
+                        y: object.y,
+                        width: object.width,
+                        height: object.height,
+                        gid: object.gid,
+                    });
+                }
+            }
+            ("group", _) => add_json_layers(map, layer.layers)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn get_children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |child| child.has_tag_name(name))
+}
+
+fn get_attribute<T: std::str::FromStr>(node: Node, attribute: &str) -> Result<T, TiledError> {
+    node.attribute(attribute)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| TiledError::Attribute {
+            element: node.tag_name().name().to_string(),
+            attribute: attribute.to_string(),
+        })
+}
+
+fn get_class<'a>(node: Node<'a, '_>) -> &'a str {
+    node.attribute("class").or_else(|| node.attribute("type")).unwrap_or_default()
+}
+
+/// Multi-line string properties keep their value as text instead.
+fn get_xml_properties(node: Node) -> Result<Properties, TiledError> {
+    get_children(node, "properties")
+        .flat_map(|properties| get_children(properties, "property"))
+        .map(|property| {
+            let name = get_attribute(property, "name")?;
+            let value = property.attribute("value").or_else(|| property.text()).unwrap_or_default();
+            Ok((name, value.to_string()))
+        })
+        .collect()
+}
+
+/// Reads a map saved by Tiled as XML (`.tmx`).
+pub fn parse_tiled_xml(source: &str) -> Result<LevelGrid, TiledError> {
+    let document = Document::parse(source)?;
+    let root = document.root_element();
+    if !root.has_tag_name("map") {
+        return Err(TiledError::MissingElement { element: "map".to_string() });
+    }
+    let mut map = TiledMap {
+        width: get_attribute(root, "width")?,
+        height: get_attribute(root, "height")?,
+        tile_width: get_attribute(root, "tilewidth")?,
+        tile_height: get_attribute(root, "tileheight")?,
+        ..Default::default()
+    };
+    for tileset in get_children(root, "tileset") {
+        if let Some(source) = tileset.attribute("source") {
+            return Err(TiledError::ExternalTileset { source: source.to_string() });
+        }
+        let first_gid: u32 = get_attribute(tileset, "firstgid")?;
+        for tile in get_children(tileset, "tile") {
+            let id: u32 = get_attribute(tile, "id")?;
+            if let Some(kind) = get_tile(get_class(tile), &get_xml_properties(tile)?) {
+                map.tiles.insert(first_gid + id, kind);
+            }
+        }
+    }
+    add_xml_layers(&mut map, root)?;
+    build_grid(&map)
+}
+
+fn add_xml_layers(map: &mut TiledMap, parent: Node) -> Result<(), TiledError> {
+    for node in parent.children().filter(Node::is_element) {
+        match node.tag_name().name() {
+            "layer" => {
+                let name = node.attribute("name").unwrap_or_default().to_string();
+                // Infinite maps split their layers into chunks.
+                let data = get_children(node, "data").next()
+                    .filter(|data| data.attribute("encoding") == Some("csv"))
+                    .filter(|data| get_children(*data, "chunk").next().is_none())
+                    .and_then(|data| parse_csv(data.text().unwrap_or_default()));
+                let Some(data) = data else { return Err(TiledError::LayerData { name }) };
+                map.layers.push(TiledLayer { name, data });
+            }
+            "objectgroup" => {
+                for object in get_children(node, "object") {
+                    map.objects.push(TiledObject {
+                        name: object.attribute("name").unwrap_or_default().to_string(),
+                        x: get_attribute(object, "x")?,
+                        y: get_attribute(object, "y")?,
+                        width: get_attribute(object, "width").unwrap_or_default(),
+                        height: get_attribute(object, "height").unwrap_or_default(),
+                        gid: get_attribute(object, "gid").ok(),
+                        tile: get_tile(get_class(object), &get_xml_properties(object)?),
+                    });
+                }
+            }
+            "group" => add_xml_layers(map, node)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parse_csv(data: &str) -> Option<Vec<u32>> {
+    data.split(',')
+        .map(str::trim)
+        .filter(|gid| !gid.is_empty())
+        .map(|gid| gid.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_TMX: &str = include_str!("../../tests/fixtures/sample.tmx");
+    const SAMPLE_TMJ: &str = include_str!("../../tests/fixtures/sample.tmj");
+    /// Walls and tile layer pickups and enemies from tile properties, then
+    /// the player, an enemy and a pickup from the object layer.
+    const SAMPLE_LEVEL: &str = "|||||\n|P**|\n|EE.|\n|||||\n";
+
+    #[test]
+    fn xml_map() {
+        assert_eq!(parse_tiled_xml(SAMPLE_TMX).unwrap(), parse_level(SAMPLE_LEVEL).unwrap());
+    }
+
+    #[test]
+    fn json_map() {
+        assert_eq!(parse_tiled_json(SAMPLE_TMJ).unwrap(), parse_level(SAMPLE_LEVEL).unwrap());
+    }
+
+    #[test]
+    fn tile_names() {
+        let property = |value: &str| vec![("tile".to_string(), value.to_string())];
+        assert_eq!(get_tile("Wall", &[]), Some(Tile::Wall));
+        assert_eq!(get_tile("", &property("StatusEffect")), Some(Tile::Speed));
+        assert_eq!(get_tile("Wall", &property("Enemy")), Some(Tile::Enemy));
+        assert_eq!(get_tile("Player", &[]), Some(Tile::Player));
+        assert_eq!(get_tile("Decoration", &[]), None);
+    }
+
+    #[test]
+    fn malformed_xml() {
+        let truncated = &SAMPLE_TMX[..SAMPLE_TMX.len() / 2];
+        assert!(matches!(parse_tiled_xml(truncated), Err(TiledError::Xml(_))));
+        assert!(matches!(parse_tiled_xml("<tileset/>"), Err(TiledError::MissingElement { .. })));
+        let no_width = SAMPLE_TMX.replace(r#" width="5""#, "");
+        assert!(matches!(parse_tiled_xml(&no_width), Err(TiledError::Attribute { .. })));
+        let base64 = SAMPLE_TMX.replace(r#"encoding="csv""#, r#"encoding="base64""#);
+        assert!(matches!(parse_tiled_xml(&base64), Err(TiledError::LayerData { .. })));
+        let short = SAMPLE_TMX.replace("1,1,1,1,1\n", "1,1,1,1\n");
+        assert!(matches!(parse_tiled_xml(&short), Err(TiledError::LayerSize { .. })));
+        let external = SAMPLE_TMX.replace(r#"name="cells""#, r#"source="cells.tsx""#);
+        assert!(matches!(parse_tiled_xml(&external), Err(TiledError::ExternalTileset { .. })));
+        let outside = SAMPLE_TMX.replace(r#"x="32" y="16""#, r#"x="320" y="16""#);
+        assert!(matches!(parse_tiled_xml(&outside), Err(TiledError::ObjectOutside { .. })));
+        let no_player = SAMPLE_TMX.replace(r#"gid="4""#, r#"gid="1""#);
+        assert!(matches!(parse_tiled_xml(&no_player),
+            Err(TiledError::Level(LevelError::MissingPlayer))));
+    }
+
+    #[test]
+    fn malformed_json() {
+        let truncated = &SAMPLE_TMJ[..SAMPLE_TMJ.len() / 2];
+        assert!(matches!(parse_tiled_json(truncated), Err(TiledError::Json(_))));
+        let data_start = SAMPLE_TMJ.find("[1,").unwrap();
+        let data_end = data_start + SAMPLE_TMJ[data_start..].find(']').unwrap() + 1;
+        let encoded = format!(r#"{}"AAAA"{}"#, &SAMPLE_TMJ[..data_start], &SAMPLE_TMJ[data_end..]);
+        assert!(matches!(parse_tiled_json(&encoded), Err(TiledError::LayerData { .. })));
+        let outside = SAMPLE_TMJ.replace(r#""x":32,"#, r#""x":320,"#);
+        assert!(matches!(parse_tiled_json(&outside), Err(TiledError::ObjectOutside { .. })));
+        let external = SAMPLE_TMJ.replace(r#""firstgid":1,"#, r#""firstgid":1, "source":"cells.tsj","#);
+        assert!(matches!(parse_tiled_json(&external), Err(TiledError::ExternalTileset { .. })));
+    }
+}
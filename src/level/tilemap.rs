@@ -0,0 +1,102 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::ImageSampler}};
+use crate::components::Wall;
+use crate::scene::{Prefabs, get_prefab_color};
+use super::grid::{LevelGrid, MEMBRANE_COLOR, TILE_SIZE, Tile, WEAK_WALL_COLOR};
+
+const CHUNK_SIZE: usize = 32;
+
+/// Draws the walls as one texture per chunk of tiles instead of one sprite
+/// per wall, rebuilding the chunks whenever the grid changes. Collision
+/// reads the grid itself, so the chunks only exist to be seen.
+pub fn build_tilemap(
+    mut commands: Commands,
+    grid: Option<Res<LevelGrid>>,
+    mut images: ResMut<Assets<Image>>,
+    query: Query<Entity, With<Wall>>,
+    scenes: Res<Assets<DynamicScene>>,
+    prefabs: Res<Prefabs>,
+) {
+    let Some(grid) = grid.filter(|grid| grid.is_changed()) else { return };
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    let wall_color = get_prefab_color(&scenes, &prefabs, "wall").unwrap_or(Color::DARK_GRAY);
+    let membrane_color = get_prefab_color(&scenes, &prefabs, "membrane").unwrap_or(MEMBRANE_COLOR);
+    let weak_wall_color = get_prefab_color(&scenes, &prefabs, "weak_wall").unwrap_or(WEAK_WALL_COLOR);
+    let get_color = |tile| match tile {
+        Tile::Wall => Some(wall_color),
+        Tile::Membrane => Some(membrane_color),
+        Tile::WeakWall => Some(weak_wall_color),
+        _ => None,
+    };
+    for chunk in get_chunks(&grid, &mut images, get_color) {
+        commands.spawn((chunk, Wall));
+    }
+}
+
+/// Sprites covering the grid in chunks, one pixel per tile. Tiles without
+/// a color are transparent and chunks with no colored tiles are skipped.
+pub fn get_chunks(
+    grid: &LevelGrid,
+    images: &mut Assets<Image>,
+    get_color: impl Fn(Tile) -> Option<Color>,
+) -> Vec<SpriteBundle> {
+    let mut chunks = Vec::new();
+    for chunk_row in (0..grid.height).step_by(CHUNK_SIZE) {
+        for chunk_col in (0..grid.width).step_by(CHUNK_SIZE) {
+            let width = CHUNK_SIZE.min(grid.width - chunk_col);
+            let height = CHUNK_SIZE.min(grid.height - chunk_row);
+            let image = get_chunk_image(grid, &get_color, chunk_col, chunk_row, width, height);
+            if let Some(image) = image {
+                let first = grid.tile_to_world(chunk_col, chunk_row);
+                let last = grid.tile_to_world(chunk_col + width - 1, chunk_row + height - 1);
+                chunks.push(SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new(
+                            width as f32 * TILE_SIZE,
+                            height as f32 * TILE_SIZE)),
+                        ..default()
+                    },
+                    texture: images.add(image),
+                    transform: Transform::from_translation((first + last) / 2.0),
+                    ..default()
+                });
+            }
+        }
+    }
+    chunks
+}
+
+fn get_chunk_image(
+    grid: &LevelGrid,
+    get_color: impl Fn(Tile) -> Option<Color>,
+    chunk_col: usize,
+    chunk_row: usize,
+    width: usize,
+    height: usize,
+) -> Option<Image> {
+    let mut is_empty = true;
+    let mut data = Vec::with_capacity(width * height * 4);
+    for row in chunk_row..chunk_row + height {
+        for col in chunk_col..chunk_col + width {
+            let color = grid.get(col, row).and_then(&get_color);
+            is_empty &= color.is_none();
+            data.extend(color.unwrap_or(Color::NONE).as_rgba_u32().to_le_bytes());
+        }
+    }
+    if is_empty {
+        return None;
+    }
+    let size = Extent3d {
+        width: width as u32,
+        height: height as u32,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new(size, TextureDimension::D2, data, TextureFormat::Rgba8UnormSrgb);
+    image.sampler_descriptor = ImageSampler::nearest();
+    Some(image)
+}
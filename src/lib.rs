@@ -0,0 +1,22 @@
+pub mod menu;
+
+pub mod editor;
+pub mod game;
+pub mod components;
+pub mod events;
+pub mod level;
+pub mod loading;
+
+pub mod util;
+
+pub mod save;
+pub mod scene;
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+pub enum AppState {
+    Loading,
+    Splash,
+    Menu,
+    Game,
+    Editor,
+}
@@ -0,0 +1,41 @@
+use bevy::{asset::LoadState, prelude::*};
+use crate::AppState;
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<RequiredAssets>()
+        .add_system_set(SystemSet::on_update(AppState::Loading)
+            .with_system(finish_loading));
+    }
+}
+
+/// Assets the game cannot start without. Plugins add theirs at startup and
+/// the splash screen opens once all of them are loaded.
+#[derive(Resource, Default)]
+pub struct RequiredAssets(pub Vec<HandleUntyped>);
+
+fn finish_loading(
+    mut state: ResMut<State<AppState>>,
+    asset_server: Res<AssetServer>,
+    required: Res<RequiredAssets>,
+    mut has_failed: Local<bool>,
+) {
+    match asset_server.get_group_load_state(required.0.iter().map(|handle| handle.id)) {
+        LoadState::Loaded => {
+            state.set(AppState::Splash).ok();
+        }
+        LoadState::Failed if !*has_failed => {
+            *has_failed = true;
+            for handle in &required.0 {
+                if asset_server.get_load_state(handle) == LoadState::Failed {
+                    let path = asset_server.get_handle_path(handle);
+                    error!("could not load {:?}", path.map(|path| path.path().to_owned()));
+                }
+            }
+        }
+        _ => {}
+    }
+}
@@ -5,27 +5,14 @@ use bevy::{
 const SCREEN_HEIGHT: f32 = 500.0;
 const SCREEN_WIDTH: f32 = 500.0;
 
-mod menu;
-
-mod game;
-mod components;
-mod events;
-mod level;
-
-mod util;
-
-mod scene;
-
-use menu::{menu::MenuPlugin, splash::SplashPlugin};
-use game::GamePlugin;
-// use scene::ScenePlugin;
-
-#[derive(Clone, Eq, PartialEq, Debug, Hash)]
-enum AppState {
-    Splash,
-    Menu,
-    Game,
-}
+use cell::AppState;
+use cell::menu::{menu::MenuPlugin, splash::SplashPlugin};
+use cell::editor::EditorPlugin;
+use cell::game::GamePlugin;
+use cell::level::LevelPlugin;
+use cell::loading::LoadingPlugin;
+use cell::save::SavePlugin;
+use cell::scene::ScenePlugin;
 
 fn main() {
     App::new()
@@ -37,13 +24,20 @@ fn main() {
                 ..default()
             },
             ..default()
+        }).set(AssetPlugin {
+            watch_for_changes: true,
+            ..default()
         }))
         .insert_resource(ClearColor(Color::BLACK))
-        .add_state(AppState::Splash)
+        .add_state(AppState::Loading)
+        .add_plugin(LoadingPlugin)
         .add_plugin(SplashPlugin)
         .add_plugin(GamePlugin)
+        .add_plugin(LevelPlugin)
+        .add_plugin(EditorPlugin)
         .add_plugin(MenuPlugin)
-        // .add_plugin(ScenePlugin)
+        .add_plugin(ScenePlugin)
+        .add_plugin(SavePlugin)
         .add_system(close_on_esc)
         .run();
 }
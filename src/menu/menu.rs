@@ -2,6 +2,7 @@ use bevy::{app::AppExit, prelude::*};
 use crate::AppState;
 use crate::events::MenuEvent;
 use crate::game::Score;
+use crate::level::{Campaign, LevelGrid, LevelSource, format_day};
 use crate::util::despawn_screen;
 use super::ui::{
     get_button_bundle,
@@ -27,16 +28,36 @@ impl Plugin for MenuPlugin {
 #[derive(Component)]
 struct Menu;
 
+#[derive(Component, Clone, Copy)]
+enum ButtonAction {
+    Next,
+    PlayAgain,
+    Quit,
+}
+
 fn spawn_screen(
     commands: Commands, 
     mut reader: EventReader<MenuEvent>,
     asset_server: Res<AssetServer>,
     score: Res<Score>,
+    campaign: Res<Campaign>,
+    grid: Option<Res<LevelGrid>>,
+    source: Res<LevelSource>,
 ) {
     if let Some(event) = reader.iter().next() {
         match event.0.as_str() {
-            "Next Level" => spawn_level_menu(commands, asset_server, &event.0, score.0),
-            _ => spawn_exit_menu(commands, asset_server, &event.0, score.0),
+            "Next Level" => {
+                let name = grid.as_ref().and_then(|grid| grid.header.name.as_deref());
+                spawn_level_menu(commands, asset_server, &campaign, &source, name, score.0);
+            }
+            _ => {
+                // Daily runs name their day so scores can be compared.
+                let day = match *source {
+                    LevelSource::Daily { day, .. } => Some(format!("Daily {}", format_day(day))),
+                    _ => None,
+                };
+                spawn_exit_menu(commands, asset_server, &event.0, day.as_deref(), score.0);
+            }
         }
     }
 }
@@ -45,18 +66,22 @@ fn spawn_exit_menu(
     mut commands: Commands, 
     asset_server: Res<AssetServer>,
     message: &str,
+    day: Option<&str>,
     score: u32,
 ) {
     commands.spawn((get_node_bundle(), Menu))
         .with_children(|parent| {
             parent.spawn(get_text_bundle(Color::WHITE, 60.0, message, &asset_server));
+            if let Some(day) = day {
+                parent.spawn(get_text_bundle(Color::WHITE, 25.0, day, &asset_server));
+            }
             parent.spawn(get_text_bundle(Color::WHITE, 25.0, "Score", &asset_server));
             parent.spawn(get_text_bundle(Color::WHITE, 50.0, &score.to_string(), &asset_server));
-            parent.spawn(get_button_bundle(Color::DARK_GRAY))
+            parent.spawn((get_button_bundle(Color::DARK_GRAY), ButtonAction::PlayAgain))
                 .with_children(|parent| {
                 parent.spawn(get_text_bundle(Color::WHITE, 40.0, "Play Again", &asset_server));
             });
-            parent.spawn(get_button_bundle(Color::DARK_GRAY))
+            parent.spawn((get_button_bundle(Color::DARK_GRAY), ButtonAction::Quit))
                 .with_children(|parent| {
                 parent.spawn(get_text_bundle(Color::WHITE, 40.0, "Quit", &asset_server));
             });
@@ -66,18 +91,41 @@ fn spawn_exit_menu(
 fn spawn_level_menu(
     mut commands: Commands, 
     asset_server: Res<AssetServer>,
-    message: &str,
+    campaign: &Campaign,
+    source: &LevelSource,
+    name: Option<&str>,
     score: u32,
 ) {
+    // Generated runs are numbered instead of taking names and par times
+    // from the campaign.
+    let (cleared, time, next) = match source {
+        LevelSource::Generated { level, .. } | LevelSource::Daily { level, .. } => (
+            format!("Level {} cleared", level + 1),
+            format!("{:.1}s", campaign.time),
+            "Next Level",
+        ),
+        _ => {
+            let level = campaign.current();
+            let next = campaign.next(score)
+                .map_or("Next Level", |index| campaign.levels[index].name.as_str());
+            (
+                format!("{} cleared", name.unwrap_or(&level.name)),
+                format!("{:.1}s (par {:.1}s)", campaign.time, level.par_time),
+                next,
+            )
+        }
+    };
     commands.spawn((get_node_bundle(), Menu))
         .with_children(|parent| {
+            parent.spawn(get_text_bundle(Color::WHITE, 60.0, &cleared, &asset_server));
+            parent.spawn(get_text_bundle(Color::WHITE, 25.0, &time, &asset_server));
             parent.spawn(get_text_bundle(Color::WHITE, 25.0, "Score", &asset_server));
             parent.spawn(get_text_bundle(Color::WHITE, 50.0, &score.to_string(), &asset_server));
-            parent.spawn(get_button_bundle(Color::DARK_GRAY))
+            parent.spawn((get_button_bundle(Color::DARK_GRAY), ButtonAction::Next))
                 .with_children(|parent| {
-                parent.spawn(get_text_bundle(Color::WHITE, 40.0, message, &asset_server));
+                parent.spawn(get_text_bundle(Color::WHITE, 40.0, next, &asset_server));
             });
-            parent.spawn(get_button_bundle(Color::DARK_GRAY))
+            parent.spawn((get_button_bundle(Color::DARK_GRAY), ButtonAction::Quit))
                 .with_children(|parent| {
                 parent.spawn(get_text_bundle(Color::WHITE, 40.0, "Quit", &asset_server));
             });
@@ -86,19 +134,15 @@ fn spawn_level_menu(
 
 fn act_button(
     mut state: ResMut<State<AppState>>,
-    query: Query<(&Interaction, &Children), Changed<Interaction>>,
-    text_query: Query<&Text>,
+    query: Query<(&Interaction, &ButtonAction), Changed<Interaction>>,
     mut event: EventWriter<AppExit>
 ) {
-    for (interaction, children) in &query {
-        let text = text_query.get(children[0]).unwrap();
+    for (interaction, action) in &query {
         if *interaction == Interaction::Clicked {
-            if text.sections[0].value == "Quit" {
-                event.send(AppExit);
-            } else if text.sections[0].value == "Play Again" {
-                state.set(AppState::Game).unwrap();
-            } else {
-                state.pop().unwrap();
+            match action {
+                ButtonAction::Quit => event.send(AppExit),
+                ButtonAction::PlayAgain => state.set(AppState::Game).unwrap(),
+                ButtonAction::Next => state.pop().unwrap(),
             }
         }
     }
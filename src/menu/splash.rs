@@ -1,5 +1,7 @@
 use bevy::prelude::*;
+use rand::prelude::random;
 use crate::AppState;
+use crate::level::{LevelSource, get_local_day};
 use crate::util::despawn_screen;
 use super::ui::{
     get_button_bundle,
@@ -24,25 +26,62 @@ impl Plugin for SplashPlugin {
 #[derive(Component)]
 struct Splash;
 
+#[derive(Component, Clone, Copy)]
+enum ButtonAction {
+    Play,
+    Random,
+    Daily,
+    Editor,
+}
+
 fn spawn_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn((get_node_bundle(), Splash))
         .with_children(|parent| {
             parent.spawn(get_text_bundle(Color::WHITE, 60.0, "C E L L", &asset_server));
-            parent.spawn(get_button_bundle(Color::DARK_GRAY))
+            parent.spawn((get_button_bundle(Color::DARK_GRAY), ButtonAction::Play))
                 .with_children(|parent| {
                 parent.spawn(get_text_bundle(Color::WHITE, 40.0, "Play", &asset_server));
             });
+            parent.spawn((get_button_bundle(Color::DARK_GRAY), ButtonAction::Random))
+                .with_children(|parent| {
+                parent.spawn(get_text_bundle(Color::WHITE, 40.0, "Random", &asset_server));
+            });
+            parent.spawn((get_button_bundle(Color::DARK_GRAY), ButtonAction::Daily))
+                .with_children(|parent| {
+                parent.spawn(get_text_bundle(Color::WHITE, 40.0, "Daily", &asset_server));
+            });
+            parent.spawn((get_button_bundle(Color::DARK_GRAY), ButtonAction::Editor))
+                .with_children(|parent| {
+                parent.spawn(get_text_bundle(Color::WHITE, 40.0, "Editor", &asset_server));
+            });
         });
 }
 
 fn interact_button(
     mut state: ResMut<State<AppState>>,
-    mut query: Query<(&Interaction, &mut BackgroundColor), 
+    mut source: ResMut<LevelSource>,
+    mut query: Query<(&Interaction, &mut BackgroundColor, &ButtonAction), 
         (Changed<Interaction>, With<Button>)>,
 ) {
-    for (interaction, mut color) in &mut query {
+    for (interaction, mut color, action) in &mut query {
         match *interaction {
-            Interaction::Clicked => { state.set(AppState::Game).unwrap(); }
+            Interaction::Clicked => {
+                match action {
+                    ButtonAction::Editor => state.set(AppState::Editor).unwrap(),
+                    ButtonAction::Random => {
+                        *source = LevelSource::Generated { seed: random(), level: 0 };
+                        state.set(AppState::Game).unwrap();
+                    }
+                    ButtonAction::Daily => {
+                        *source = LevelSource::Daily { day: get_local_day(), level: 0 };
+                        state.set(AppState::Game).unwrap();
+                    }
+                    ButtonAction::Play => {
+                        *source = LevelSource::Assets;
+                        state.set(AppState::Game).unwrap();
+                    }
+                }
+            }
             Interaction::Hovered => { *color = Color::ORANGE_RED.into(); }
             Interaction::None => { *color = Color::DARK_GRAY.into(); }
         }
@@ -0,0 +1,279 @@
+use bevy::{
+    ecs::entity::EntityMap,
+    prelude::*,
+    scene::serde::SceneDeserializer};
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
+use std::{any::type_name, error::Error, fs};
+use crate::AppState;
+use crate::components::*;
+use crate::game::{EXPLOSION_TEXTURE, PARTICLE_TEXTURE, Score};
+use crate::level::{
+    Campaign, CurrentLevel, LevelAsset, LevelEntity, LevelGrid, LevelSource, Tile, WallHealth,
+    parse_level};
+use crate::menu::ui::get_text_bundle;
+use crate::util::despawn_screen;
+
+const SAVE_DIR: &str = "saves";
+const SAVE_PATH: &str = "saves/quick.ron";
+const NOTICE_SECONDS: f32 = 2.0;
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<SaveStatus>()
+        .add_system_set(SystemSet::on_update(AppState::Game)
+            .with_system(quick_save)
+            .with_system(quick_load)
+            .with_system(show_notice.after(quick_save).after(quick_load))
+            .with_system(despawn_notice))
+        .add_system_set(SystemSet::on_pause(AppState::Game)
+            .with_system(despawn_screen::<SaveNotice>))
+        .add_system_set(SystemSet::on_exit(AppState::Game)
+            .with_system(despawn_screen::<SaveNotice>));
+    }
+}
+
+/// The outcome of the last quick save or load, for `show_notice`.
+#[derive(Resource, Default)]
+struct SaveStatus(Option<String>);
+
+#[derive(Component)]
+struct SaveNotice(Timer);
+
+/// Everything needed to put a game back the way it was. The walls travel
+/// with the level text, since they are drawn from the grid.
+#[derive(Serialize, Deserialize)]
+struct SaveGame {
+    level: String,
+    source: SavedSource,
+    campaign_index: usize,
+    /// `Campaign::best_times`, which unlock later levels.
+    best_times: Vec<Option<f32>>,
+    time: f32,
+    score: u32,
+    /// `(col, row, health)` of the weak walls still standing.
+    wall_health: Vec<(usize, usize, i32)>,
+    /// A `DynamicScene` of the level's entities, in RON.
+    scene: String,
+}
+
+#[derive(Serialize, Deserialize)]
+enum SavedSource {
+    Assets,
+    Generated { seed: u64, level: usize },
+    Daily { day: i64, level: usize },
+}
+
+/// Components written to a save. Render state is left out and rebuilt by
+/// `add_render_components` when loading.
+fn get_saved_components() -> [&'static str; 14] {
+    [
+        type_name::<Transform>(),
+        type_name::<Sprite>(),
+        type_name::<Cell>(),
+        type_name::<Player>(),
+        type_name::<Enemy>(),
+        type_name::<Lifespan>(),
+        type_name::<Velocity>(),
+        type_name::<StatusEffect>(),
+        type_name::<StatusEffects>(),
+        type_name::<TilePosition>(),
+        type_name::<Hostile>(),
+        type_name::<Explosion>(),
+        type_name::<Virus>(),
+        type_name::<Particle>(),
+    ]
+}
+
+fn quick_save(world: &mut World) {
+    if !world.resource::<Input<KeyCode>>().just_pressed(KeyCode::F5) {
+        return;
+    }
+    let status = match save_game(world) {
+        Ok(()) => {
+            info!("saved game to {SAVE_PATH}");
+            "Game saved".to_string()
+        }
+        Err(error) => {
+            warn!("could not save game: {error}");
+            format!("Not saved: {error}")
+        }
+    };
+    world.resource_mut::<SaveStatus>().0 = Some(status);
+}
+
+fn quick_load(world: &mut World) {
+    if !world.resource::<Input<KeyCode>>().just_pressed(KeyCode::F9) {
+        return;
+    }
+    let status = match load_game(world) {
+        Ok(()) => {
+            info!("loaded game from {SAVE_PATH}");
+            "Game loaded".to_string()
+        }
+        Err(error) => {
+            warn!("could not load game: {error}");
+            format!("Not loaded: {error}")
+        }
+    };
+    world.resource_mut::<SaveStatus>().0 = Some(status);
+}
+
+fn show_notice(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut status: ResMut<SaveStatus>,
+    query: Query<Entity, With<SaveNotice>>,
+) {
+    let Some(message) = status.0.take() else { return };
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.spawn((
+        get_text_bundle(Color::WHITE, 16.0, &message, &asset_server)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        SaveNotice(Timer::from_seconds(NOTICE_SECONDS, TimerMode::Once)),
+    ));
+}
+
+fn despawn_notice(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut SaveNotice)>,
+) {
+    for (entity, mut notice) in query.iter_mut() {
+        if notice.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn save_game(world: &mut World) -> Result<(), Box<dyn Error>> {
+    let source = match world.resource::<LevelSource>() {
+        LevelSource::Assets => SavedSource::Assets,
+        LevelSource::Generated { seed, level } => SavedSource::Generated { seed: *seed, level: *level },
+        LevelSource::Daily { day, level } => SavedSource::Daily { day: *day, level: *level },
+        // A test play is saved from the editor, as a level.
+        LevelSource::Playtest(_) => return Err("test plays cannot be saved".into()),
+    };
+    let mut grid = world.get_resource::<LevelGrid>().ok_or("no level is loaded")?.clone();
+    // Weak wall legend entries stop lining up with the walls once some are
+    // destroyed, so their health is saved on its own.
+    grid.header.legend.retain(|(glyph, _), _| *glyph != Tile::WeakWall.glyph());
+    let wall_health = world.resource::<WallHealth>().0.iter()
+        .map(|(&(col, row), &health)| (col, row, health))
+        .collect();
+    // Wall chunks are rebuilt from the level text.
+    let entities: Vec<Entity> = world.query_filtered::<Entity, (LevelEntity, Without<Wall>)>()
+        .iter(world)
+        .collect();
+    let mut builder = DynamicSceneBuilder::from_world(world);
+    builder.extract_entities(entities.into_iter());
+    let mut scene = builder.build();
+    let saved_components = get_saved_components();
+    for entity in &mut scene.entities {
+        entity.components.retain(|component| saved_components.contains(&component.type_name()));
+    }
+    let campaign = world.resource::<Campaign>();
+    let save = SaveGame {
+        level: grid.to_string(),
+        source,
+        campaign_index: campaign.index,
+        best_times: campaign.best_times.clone(),
+        time: campaign.time,
+        score: world.resource::<Score>().0,
+        wall_health,
+        scene: scene.serialize_ron(world.resource::<AppTypeRegistry>())?,
+    };
+    fs::create_dir_all(SAVE_DIR)?;
+    fs::write(SAVE_PATH, ron::ser::to_string_pretty(&save, Default::default())?)?;
+    Ok(())
+}
+
+/// Replaces the running level with the saved one. Nothing is touched
+/// unless the whole save reads back.
+fn load_game(world: &mut World) -> Result<(), Box<dyn Error>> {
+    // Leaving a test play would lose the way back to the unsaved edits.
+    if let LevelSource::Playtest(_) = world.resource::<LevelSource>() {
+        return Err("saves cannot be loaded during a test play".into());
+    }
+    let save: SaveGame = ron::from_str(&fs::read_to_string(SAVE_PATH)?)?;
+    let grid = parse_level(&save.level)?;
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let mut deserializer = ron::de::Deserializer::from_str(&save.scene)?;
+    let scene = SceneDeserializer { type_registry: &registry.read() }
+        .deserialize(&mut deserializer)?;
+    let entities: Vec<Entity> = world.query_filtered::<Entity, (LevelEntity, Without<Wall>)>()
+        .iter(world)
+        .collect();
+    for entity in entities {
+        world.despawn(entity);
+    }
+    let source = match save.source {
+        SavedSource::Assets => LevelSource::Assets,
+        SavedSource::Generated { seed, level } => LevelSource::Generated { seed, level },
+        SavedSource::Daily { day, level } => LevelSource::Daily { day, level },
+    };
+    let mut campaign = world.resource_mut::<Campaign>();
+    campaign.index = save.campaign_index.min(campaign.levels.len() - 1);
+    campaign.time = save.time;
+    let level_count = campaign.levels.len();
+    campaign.best_times = save.best_times;
+    campaign.best_times.resize(level_count, None);
+    let campaign_handle = campaign.handles[campaign.index].clone();
+    let current = world.resource::<CurrentLevel>().0.clone();
+    let is_playing = matches!(
+        world.resource::<Assets<LevelAsset>>().get(&current), Some(asset) if asset.0 == grid);
+    // Adding the grid as a new asset would make reload_level respawn the
+    // level over the restored entities, so a generated level only stays
+    // hot-reloadable when it is the one already being played.
+    let handle = match source {
+        LevelSource::Assets => campaign_handle,
+        _ if is_playing => current,
+        _ => Handle::default(),
+    };
+    world.resource_mut::<CurrentLevel>().0 = handle;
+    world.insert_resource(source);
+    world.insert_resource(Score(save.score));
+    world.insert_resource(grid);
+    let wall_health = save.wall_health.into_iter()
+        .map(|(col, row, health)| ((col, row), health))
+        .collect();
+    world.insert_resource(WallHealth(wall_health));
+    let mut entity_map = EntityMap::default();
+    scene.write_to_world(world, &mut entity_map)?;
+    for entity in entity_map.values() {
+        add_render_components(world, entity);
+    }
+    Ok(())
+}
+
+fn add_render_components(world: &mut World, entity: Entity) {
+    let texture_path = if world.get::<Particle>(entity).is_some() {
+        Some(PARTICLE_TEXTURE)
+    } else if world.get::<Explosion>(entity).is_some() {
+        Some(EXPLOSION_TEXTURE)
+    } else {
+        None
+    };
+    let texture: Handle<Image> = match texture_path {
+        Some(path) => world.resource::<AssetServer>().load(path),
+        None => Handle::default(),
+    };
+    world.entity_mut(entity).insert((
+        texture,
+        GlobalTransform::default(),
+        Visibility::default(),
+        ComputedVisibility::default(),
+    ));
+}
@@ -1,77 +1,120 @@
-// use bevy::{prelude::*, tasks::IoTaskPool, utils::Duration};
-// use std::{fs::File, io::Write};
-//
-// use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
-// use crate::components::{
-//     MainCamera, 
-//     Cell, Player, Direction, Hostile, Enemy, Lifespan,
-//     Explosion, Virus, Particle, Wall};
-//
-// pub struct ScenePlugin;
-//
-// impl Plugin for ScenePlugin {
-//     fn build(&self, app: &mut App) {
-//         app
-//             .register_type::<Wall>()
-//             .register_type::<Option<Rect>>()
-//             .register_type::<Option<Vec2>>()
-//             .register_type_data::<Option<Vec2>, ReflectSerialize>()
-//             .register_type_data::<Option<Vec2>, ReflectDeserialize>()
-//             .register_type_data::<Sprite, ReflectComponent>()
-//             .register_type::<Sprite>()
-//             .add_startup_system(save_scene)
-//             .add_startup_system(load_scene.after(save_scene))
-//             .add_system(log)
-//             .run();
-//     }
-// }
-//
-// fn load_scene(mut commands: Commands, asset_server: Res<AssetServer>) { 
-//     commands.spawn(DynamicSceneBundle {
-//         scene: asset_server.load("scenes/wall.scn.ron"),
-//          
-//         ..default()
-//     });
-// }
-//
-// fn save_scene(world: &mut World) {
-//     let size = 20.0;
-//     let top_translation = Vec3::new(0.0, SCREEN_HEIGHT / 2.0 - (size / 2.0), 1.0);
-//     let bottom_translation = Vec3::new(0.0, -SCREEN_HEIGHT / 2.0 + (size / 2.0), 1.0);
-//     let left_translation = Vec3::new(-SCREEN_WIDTH / 2.0 + (size / 2.0), 0.0, 1.0);
-//     let right_translation = Vec3::new(SCREEN_WIDTH / 2.0 - (size / 2.0), 0.0, 1.0);
-//     world.spawn((get_rectangle(Color::DARK_GRAY, SCREEN_WIDTH, size, top_translation), Wall));
-//     world.spawn((get_rectangle(Color::DARK_GRAY, SCREEN_WIDTH, size, bottom_translation), Wall));
-//     world.spawn((get_rectangle(Color::DARK_GRAY, size, SCREEN_HEIGHT, left_translation), Wall));
-//     world.spawn((get_rectangle(Color::DARK_GRAY, size, SCREEN_HEIGHT, right_translation), Wall));
-//     let type_registry = world.resource::<AppTypeRegistry>();
-//     let scene = DynamicScene::from_world(&world, type_registry);
-//     let serialized_scene = scene.serialize_ron(type_registry).unwrap();
-//     info!("=================================================================");
-//     info!("{}", serialized_scene);
-//
-//     #[cfg(not(target_arch = "wasm32"))]
-//     IoTaskPool::get().spawn(async move {
-//         File::create("assets/scenes/box.scn.ron")
-//             .and_then(|mut file| file.write(serialized_scene.as_bytes()))
-//             .expect("Error writing scene to file");
-//     }).detach();
-// }
-//
-// fn get_rectangle(color: Color, height: f32, width: f32, translation: Vec3) -> SpriteBundle {
-//     SpriteBundle {
-//         sprite: Sprite {
-//             color,
-//             ..default()
-//         },
-//         transform: Transform::from_scale(Vec3::new(height, width, 1.0))
-//             .with_translation(translation),
-//         ..default()
-//     }
-// }
-//
-// fn log(query: Query<(Entity, &Wall), Changed<Wall>>) { 
-//     for (entity, wall) in &query {
-//         info!("  Wall Entity({})", entity.index());
-//     }
-// }
+use bevy::{prelude::*, scene::DynamicEntity, utils::HashMap};
+use crate::components::*;
+use crate::loading::RequiredAssets;
+
+/// Scene files whose first entity is the archetype of a level tile. The
+/// level spawners copy its components onto each entity they spawn.
+const PREFAB_PATHS: [(&str, &str); 6] = [
+    ("player", "scenes/player.scn.ron"),
+    ("enemy", "scenes/enemy.scn.ron"),
+    ("speed", "scenes/speed.scn.ron"),
+    ("wall", "scenes/wall.scn.ron"),
+    ("membrane", "scenes/membrane.scn.ron"),
+    ("weak_wall", "scenes/weak_wall.scn.ron"),
+];
+
+pub struct ScenePlugin;
+
+impl Plugin for ScenePlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .register_type::<Cell>()
+        .register_type::<Player>()
+        .register_type::<Enemy>()
+        .register_type::<Lifespan>()
+        .register_type::<Velocity>()
+        .register_type::<Wall>()
+        .register_type::<StatusEffect>()
+        .register_type::<ActiveEffect>()
+        .register_type::<Vec<ActiveEffect>>()
+        .register_type::<StatusEffects>()
+        .register_type::<TilePosition>()
+        .register_type::<MainCamera>()
+        .register_type::<Hostile>()
+        .register_type::<Explosion>()
+        .register_type::<Virus>()
+        .register_type::<Particle>()
+        .register_type::<TimerMode>()
+        .register_type::<Color>()
+        .register_type_data::<Sprite, ReflectComponent>()
+        .register_type::<Option<Rect>>()
+        .register_type::<Option<Vec2>>()
+        .register_type_data::<Option<Vec2>, ReflectSerialize>()
+        .register_type_data::<Option<Vec2>, ReflectDeserialize>()
+        .add_startup_system(load_prefabs);
+    }
+}
+
+#[derive(Resource)]
+pub struct Prefabs(pub HashMap<&'static str, Handle<DynamicScene>>);
+
+/// Levels spawn bare sprites without their prefabs, so the game waits for
+/// all of them to load.
+fn load_prefabs(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut required: ResMut<RequiredAssets>,
+) {
+    let prefabs: HashMap<_, Handle<DynamicScene>> = PREFAB_PATHS.iter()
+        .map(|(name, path)| (*name, asset_server.load(*path)))
+        .collect();
+    required.0.extend(prefabs.values().map(|handle| handle.clone_untyped()));
+    commands.insert_resource(Prefabs(prefabs));
+}
+
+/// Spawns a sprite at `translation` that takes on the components of the
+/// named prefab, except for its translation, once commands are applied.
+/// Components inserted after this call override the prefab's.
+pub fn spawn_prefab(commands: &mut Commands, name: &'static str, translation: Vec3) -> Entity {
+    let entity = commands.spawn(SpriteBundle {
+        transform: Transform::from_translation(translation),
+        ..default()
+    }).id();
+    commands.add(move |world: &mut World| {
+        apply_prefab(world, name, entity);
+        if let Some(mut transform) = world.get_mut::<Transform>(entity) {
+            transform.translation = translation;
+        }
+    });
+    entity
+}
+
+fn apply_prefab(world: &mut World, name: &str, entity: Entity) {
+    world.resource_scope(|world, scenes: Mut<Assets<DynamicScene>>| {
+        let Some(prefab) = get_prefab(&scenes, world.resource::<Prefabs>(), name) else {
+            warn!("prefab '{name}' is not loaded");
+            return;
+        };
+        let registry = world.resource::<AppTypeRegistry>().clone();
+        let registry = registry.read();
+        for component in &prefab.components {
+            let reflect_component = registry.get_with_name(component.type_name())
+                .and_then(|registration| registration.data::<ReflectComponent>());
+            match reflect_component {
+                Some(reflect_component) => {
+                    reflect_component.apply_or_insert(world, entity, &**component);
+                }
+                None => warn!("prefab '{name}' has unregistered component {}", component.type_name()),
+            }
+        }
+    });
+}
+
+fn get_prefab<'a>(
+    scenes: &'a Assets<DynamicScene>,
+    prefabs: &Prefabs,
+    name: &str,
+) -> Option<&'a DynamicEntity> {
+    prefabs.0.get(name)
+        .and_then(|handle| scenes.get(handle))
+        .and_then(|scene| scene.entities.first())
+}
+
+/// The sprite color of a prefab, for things drawn without spawning it.
+pub fn get_prefab_color(scenes: &Assets<DynamicScene>, prefabs: &Prefabs, name: &str) -> Option<Color> {
+    let component = get_prefab(scenes, prefabs, name)?.components.iter()
+        .find(|component| component.type_name() == std::any::type_name::<Sprite>())?;
+    let mut sprite = Sprite::default();
+    sprite.apply(&**component);
+    Some(sprite.color)
+}